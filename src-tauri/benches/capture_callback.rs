@@ -0,0 +1,51 @@
+//! Times one audio callback: downmixing a device buffer into the capture
+//! ring, as `build_stream` does, at common device buffer sizes.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[allow(dead_code)]
+#[path = "../src/queue.rs"]
+mod queue;
+#[allow(dead_code)]
+#[path = "../src/sample.rs"]
+mod sample;
+
+use queue::CaptureRing;
+
+const SAMPLE_RATE: usize = 48_000;
+const CHANNELS: usize = 2;
+const BUFFER_FRAMES: [usize; 3] = [128, 480, 1024];
+
+fn capture_callback(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capture_callback");
+    for frames in BUFFER_FRAMES {
+        let data: Vec<f32> = (0..frames * CHANNELS)
+            .map(|n| (n as f32 * 0.01).sin() * 0.5)
+            .collect();
+        // 1 s, as the recorder sizes it
+        let ring = CaptureRing::new(SAMPLE_RATE);
+        let mut drained = Vec::with_capacity(SAMPLE_RATE);
+        group.throughput(Throughput::Elements(frames as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(frames), &data, |b, data| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    ring.push_frames(black_box(data), CHANNELS, None);
+                    elapsed += start.elapsed();
+                    // The bridge thread empties the ring between callbacks
+                    ring.drain_into(&mut drained);
+                    drained.clear();
+                }
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, capture_callback);
+criterion_main!(benches);
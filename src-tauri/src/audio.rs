@@ -1,39 +1,259 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{mpsc as std_mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::traits::{DeviceTrait, StreamTrait};
 use cpal::{
-    Device, Sample, SampleFormat, SizedSample, Stream, StreamConfig, SupportedStreamConfig,
+    Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig, StreamError,
+    SupportedStreamConfig, I24,
 };
 use parking_lot::Mutex;
-use tokio::sync::mpsc;
-use tokio::sync::mpsc::error::TrySendError;
+use serde::Serialize;
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 
+use crate::devices::{select_input_device, SelectedDevice};
+use crate::dsp::{Resampler, VoiceProcessor};
 use crate::errors::{AppError, AppResult};
+use crate::meter::{LevelEvent, LevelMeter, LevelReading, LevelStats, LevelWarning};
+use crate::queue::{audio_queue, AudioReceiver, AudioSender, CaptureRing};
+use crate::sample::{to_pcm16, FullScale};
+use crate::settings::ProcessingSettings;
+use crate::vad::{EnergyVad, VadCallback};
 
 const READY_TIMEOUT: Duration = Duration::from_secs(3);
-const CHUNK_CHANNEL_CAPACITY: usize = 64;
+/// Duration of the chunks handed to the backend and the detector.
+const FRAME_DURATION: Duration = Duration::from_millis(20);
+/// Audio the callback ring holds for the bridge thread.
+const RING_DURATION: Duration = Duration::from_secs(1);
+/// How long the bridge sleeps when the ring is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Upper bound for chunks merged while the backend is backed up.
+const MAX_MERGED_CHUNK: Duration = Duration::from_millis(500);
+/// A running stream that delivers nothing for this long is treated as lost;
+/// some backends stop calling back without reporting an error.
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+/// Device switches allowed per session before capture gives up.
+const MAX_FAILOVERS: u32 = 3;
+/// Gives the OS time to settle on a new default device after a removal.
+const FAILOVER_DELAY: Duration = Duration::from_millis(300);
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct RecorderRequest {
+    /// Preferred device rate; the device default is used when unsupported.
     pub sample_rate: u32,
+    /// Rate of the PCM16 chunks handed to the backend, whatever the device runs at.
+    pub output_rate: u32,
+    /// Channel to record (0-based); `None` averages all channels.
+    pub channel: Option<u16>,
+    /// Identifier from `list_input_devices` or a device name; `None` uses the default.
     pub input_device: Option<String>,
+    /// Audio the queue to the backend holds before dropping the oldest.
+    pub buffer: Duration,
+    /// Dropped share of the audio, in percent, at which the session fails.
+    pub max_loss_percent: f32,
+    /// High-pass, noise suppression and gain control stages to apply.
+    pub processing: ProcessingSettings,
+    /// Runs voice activity detection on the captured audio when set.
+    pub vad: Option<LocalVad>,
+    /// Receives levels, warnings, device switches and failures during the
+    /// session.
+    pub on_event: CaptureCallback,
+}
+
+/// Parameters for the energy-based detector in the capture thread.
+#[derive(Clone)]
+pub struct LocalVad {
+    pub trailing_silence_ms: u32,
+    pub margin_db: f32,
+    pub on_event: VadCallback,
+}
+
+/// The input device changed while recording.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceSwitch {
+    pub lost_device: Option<String>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CaptureEvent {
+    /// Throttled input level for a VU meter.
+    Level(LevelReading),
+    /// Sustained silence, clipping or DC offset in the input.
+    Warning(LevelWarning),
+    /// The device went away and recording continues on the default device.
+    DeviceSwitched(DeviceSwitch),
+    /// Capture ended early: no device could take over after a loss, or too
+    /// much audio was dropped. The audio recorded so far still reaches the
+    /// backend.
+    Failed(AppError),
+}
+
+pub type CaptureCallback = Arc<dyn Fn(CaptureEvent) + Send + Sync>;
+
+/// Keeps the device open between sessions with a rolling pre-roll buffer.
+/// Sessions whose request matches reuse the open stream and start with the
+/// buffered audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarmRequest {
+    pub sample_rate: u32,
+    pub output_rate: u32,
+    pub channel: Option<u16>,
+    pub input_device: Option<String>,
+    pub preroll: Duration,
+    /// The mic closes after this long without a session.
+    pub idle_timeout: Duration,
+}
+
+impl WarmRequest {
+    fn serves(&self, request: &RecorderRequest) -> bool {
+        self.sample_rate == request.sample_rate
+            && self.output_rate == request.output_rate
+            && self.channel == request.channel
+            && self.input_device == request.input_device
+    }
+}
+
+/// Whether the warm mic holds the microphone open.
+#[derive(Debug, Clone, Serialize)]
+pub struct MicState {
+    pub open: bool,
+    pub device_name: Option<String>,
+}
+
+pub type MicCallback = Arc<dyn Fn(MicState) + Send + Sync>;
+
+/// What the capture thread actually opened.
+#[derive(Clone, Debug)]
+pub struct CaptureInfo {
+    /// Rate of the delivered chunks, equal to `RecorderRequest::output_rate`.
+    pub sample_rate: u32,
+    pub device_sample_rate: u32,
+    pub device_name: Option<String>,
+    pub device_id: Option<String>,
+    /// Configured device that was not found; the default device was used.
+    pub missing_device: Option<String>,
+}
+
+/// Audio statistics reported with the session summary.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureStats {
+    /// Audio dropped because the backend fell behind.
+    pub dropped_ms: u64,
+    /// Longest backlog waiting for the backend.
+    pub peak_backlog_ms: u64,
+    pub levels: LevelStats,
+}
+
+/// Shared between the capture thread and `RecorderService::stop`.
+#[derive(Default)]
+struct CaptureReport {
+    error: Option<AppError>,
+    stats: CaptureStats,
+}
+
+/// Returned by `RecorderService::stop`.
+#[derive(Debug)]
+pub struct CaptureSummary {
+    pub duration: Duration,
+    /// Set when capture ended early, e.g. because the device was lost.
+    pub error: Option<AppError>,
+    pub stats: CaptureStats,
+}
+
+/// Metadata about a running session, kept until `stop_recording` stores it.
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub model: String,
+    pub language: Option<String>,
+    pub sample_rate: u32,
+    pub input_device: Option<String>,
+    pub source_app: Option<String>,
+}
+
+type SessionSlot = (JoinHandle<AppResult<String>>, SessionInfo);
+
+/// Ties a capture run to its session, whichever thread runs it.
+struct SessionChannels {
+    tx: AudioSender,
+    stop: Arc<AtomicBool>,
+    ready: std_mpsc::Sender<AppResult<CaptureInfo>>,
+    report: Arc<Mutex<CaptureReport>>,
+    /// Dropped, with any clones, when capture for the session has finished.
+    done: std_mpsc::Sender<()>,
+}
+
+type Handoff = std_mpsc::Sender<(RecorderRequest, SessionChannels)>;
+
+struct WarmHandle {
+    request: WarmRequest,
+    shared: Arc<WarmShared>,
+}
+
+impl Drop for WarmHandle {
+    /// Closing the handoff stops the warm thread once its session is done.
+    fn drop(&mut self) {
+        self.shared.handoff.lock().take();
+    }
+}
+
+impl WarmHandle {
+    /// Whether the stream is on the device the request resolves to now. Only
+    /// re-resolves, which enumerates devices, while it is on a fallback.
+    fn on_requested_device(&self) -> bool {
+        if !self.shared.on_fallback.load(Ordering::SeqCst) {
+            return true;
+        }
+        let host = cpal::default_host();
+        select_input_device(&host, self.request.input_device.as_deref())
+            .is_ok_and(|selected| selected.id == *self.shared.device_id.lock())
+    }
+}
+
+/// State a warm mic thread shares with its handle.
+struct WarmShared {
+    /// Taken by whichever side closes the warm mic first. Sessions are handed
+    /// over under this lock, so none arrives after the thread stopped taking
+    /// them.
+    handoff: Mutex<Option<Handoff>>,
+    device_id: Mutex<Option<String>>,
+    /// The stream is not on the device it was armed for: that one was
+    /// missing, or a session failed over to the default device.
+    on_fallback: AtomicBool,
+}
+
+impl WarmShared {
+    fn is_open(&self) -> bool {
+        self.handoff.lock().is_some()
+    }
+
+    fn record_device(&self, active: &ActiveStream) {
+        let mut device_id = self.device_id.lock();
+        if *device_id != active.device_id {
+            device_id.clone_from(&active.device_id);
+            self.on_fallback.store(true, Ordering::SeqCst);
+        }
+    }
 }
 
 struct ActiveRecorder {
-    bridge: thread::JoinHandle<()>,
+    done: std_mpsc::Receiver<()>,
     stop: Arc<AtomicBool>,
     started_at: Instant,
-    receiver: Option<mpsc::Receiver<Vec<i16>>>,
+    receiver: Option<AudioReceiver>,
+    report: Arc<Mutex<CaptureReport>>,
 }
 
 pub struct RecorderService {
     inner: Mutex<Option<ActiveRecorder>>,
-    session: Mutex<Option<JoinHandle<AppResult<String>>>>,
+    session: Mutex<Option<SessionSlot>>,
+    session_counter: AtomicU64,
+    warm: Mutex<Option<WarmHandle>>,
 }
 
 impl RecorderService {
@@ -41,41 +261,43 @@ impl RecorderService {
         Self {
             inner: Mutex::new(None),
             session: Mutex::new(None),
+            session_counter: AtomicU64::new(0),
+            warm: Mutex::new(None),
         }
     }
 
-    pub fn start(&self, request: RecorderRequest) -> AppResult<u32> {
+    pub fn start(&self, request: RecorderRequest) -> AppResult<CaptureInfo> {
         let mut guard = self.inner.lock();
         if guard.is_some() {
             return Err(AppError::RecorderBusy);
         }
 
-        let (chunk_tx, chunk_rx) = mpsc::channel(CHUNK_CHANNEL_CAPACITY);
+        let rate = f64::from(request.output_rate);
+        let (chunk_tx, chunk_rx) = audio_queue(
+            (request.buffer.as_secs_f64() * rate) as usize,
+            (MAX_MERGED_CHUNK.as_secs_f64() * rate) as usize,
+        );
         let stop = Arc::new(AtomicBool::new(false));
-        let bridge_stop = stop.clone();
-        let device_name = request.input_device.clone();
-        let desired_sample_rate = request.sample_rate;
+        let report = Arc::new(Mutex::new(CaptureReport::default()));
         let (ready_tx, ready_rx) = std_mpsc::channel();
+        let (done_tx, done_rx) = std_mpsc::channel();
+        let channels = SessionChannels {
+            tx: chunk_tx,
+            stop: stop.clone(),
+            ready: ready_tx,
+            report: report.clone(),
+            done: done_tx,
+        };
 
-        let bridge = thread::Builder::new()
-            .name("audio-bridge".into())
-            .spawn(move || {
-                if let Err(err) = capture_loop(
-                    device_name,
-                    desired_sample_rate,
-                    chunk_tx,
-                    bridge_stop.clone(),
-                    ready_tx.clone(),
-                ) {
-                    error!(error = %err, "audio capture failed");
-                    let _ = ready_tx.send(Err(err));
-                    bridge_stop.store(true, Ordering::SeqCst);
-                }
-            })
-            .map_err(|err| AppError::AudioInit(err.to_string()))?;
+        if let Some((request, channels)) = self.begin_warm(request, channels) {
+            thread::Builder::new()
+                .name("audio-bridge".into())
+                .spawn(move || run_cold(request, channels))
+                .map_err(|err| AppError::AudioInit(err.to_string()))?;
+        }
 
-        let sample_rate = match ready_rx.recv_timeout(READY_TIMEOUT) {
-            Ok(Ok(rate)) => rate,
+        let info = match ready_rx.recv_timeout(READY_TIMEOUT) {
+            Ok(Ok(info)) => info,
             Ok(Err(err)) => return Err(err),
             Err(_) => {
                 return Err(AppError::AudioInit(
@@ -85,215 +307,736 @@ impl RecorderService {
         };
 
         *guard = Some(ActiveRecorder {
-            bridge,
+            done: done_rx,
             stop,
             started_at: Instant::now(),
             receiver: Some(chunk_rx),
+            report,
         });
 
-        Ok(sample_rate)
+        Ok(info)
     }
 
-    pub fn take_receiver(&self) -> Option<mpsc::Receiver<Vec<i16>>> {
+    pub fn take_receiver(&self) -> Option<AudioReceiver> {
         self.inner
             .lock()
             .as_mut()
             .and_then(|active| active.receiver.take())
     }
 
-    pub async fn stop(&self) -> AppResult<Option<Duration>> {
+    pub async fn stop(&self) -> AppResult<Option<CaptureSummary>> {
         let handle = {
             let mut guard = self.inner.lock();
             guard.take().map(|active| {
                 active.stop.store(true, Ordering::SeqCst);
-                (active.bridge, active.started_at)
+                (active.done, active.started_at, active.report)
             })
         };
 
-        if let Some((handle, started_at)) = handle {
+        if let Some((done, started_at, report)) = handle {
             tokio::task::spawn_blocking(move || {
-                let _ = handle.join();
+                // Returns once the capture side drops its sender
+                let _ = done.recv();
             })
             .await
             .map_err(|err| AppError::AudioInit(err.to_string()))?;
-            Ok(Some(started_at.elapsed()))
+            let report = std::mem::take(&mut *report.lock());
+            Ok(Some(CaptureSummary {
+                duration: started_at.elapsed(),
+                error: report.error,
+                stats: report.stats,
+            }))
         } else {
             Ok(None)
         }
     }
 
+    /// Signals the capture thread to stop without tearing down the session, so
+    /// a later `stop` still reports the duration and the session error.
+    pub fn abort_capture(&self) {
+        if let Some(active) = self.inner.lock().as_ref() {
+            active.stop.store(true, Ordering::SeqCst);
+        }
+    }
+
     pub fn is_recording(&self) -> bool {
         self.inner.lock().is_some()
     }
 
-    pub fn attach_session(&self, handle: JoinHandle<AppResult<String>>) -> AppResult<()> {
+    pub fn attach_session(
+        &self,
+        handle: JoinHandle<AppResult<String>>,
+        info: SessionInfo,
+    ) -> AppResult<()> {
         let mut guard = self.session.lock();
         if guard.is_some() {
             return Err(AppError::RecorderBusy);
         }
-        *guard = Some(handle);
+        *guard = Some((handle, info));
         Ok(())
     }
 
-    pub fn take_session(&self) -> Option<JoinHandle<AppResult<String>>> {
+    /// Opens the microphone ahead of sessions, replacing a warm mic armed with
+    /// different parameters. Blocks until the device is open.
+    pub fn arm(&self, request: WarmRequest, on_mic: MicCallback) -> AppResult<()> {
+        let mut warm = self.warm.lock();
+        if warm.as_ref().is_some_and(|handle| {
+            handle.request == request && handle.shared.is_open() && handle.on_requested_device()
+        }) {
+            return Ok(());
+        }
+        // Dropping the handle closes the old thread's session channel
+        *warm = None;
+
+        let (sessions_tx, sessions_rx) = std_mpsc::channel();
+        let (ready_tx, ready_rx) = std_mpsc::channel();
+        let shared = Arc::new(WarmShared {
+            handoff: Mutex::new(Some(sessions_tx)),
+            device_id: Mutex::new(None),
+            on_fallback: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        let thread_request = request.clone();
+        thread::Builder::new()
+            .name("warm-mic".into())
+            .spawn(move || warm_loop(thread_request, sessions_rx, ready_tx, on_mic, thread_shared))
+            .map_err(|err| AppError::AudioInit(err.to_string()))?;
+        match ready_rx.recv_timeout(READY_TIMEOUT) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => return Err(err),
+            Err(_) => {
+                return Err(AppError::AudioInit(
+                    "audio device initialization timed out".into(),
+                ))
+            }
+        }
+        *warm = Some(WarmHandle { request, shared });
+        Ok(())
+    }
+
+    /// Closes the warm mic; a session running on it finishes first.
+    pub fn disarm(&self) {
+        self.warm.lock().take();
+    }
+
+    pub fn is_warm(&self) -> bool {
+        self.warm
+            .lock()
+            .as_ref()
+            .is_some_and(|handle| handle.shared.is_open())
+    }
+
+    /// Hands the session to the warm mic if it serves the request; otherwise
+    /// gives the request back for a cold start.
+    fn begin_warm(
+        &self,
+        request: RecorderRequest,
+        channels: SessionChannels,
+    ) -> Option<(RecorderRequest, SessionChannels)> {
+        let mut warm = self.warm.lock();
+        let Some(handle) = warm
+            .as_ref()
+            .filter(|handle| handle.request.serves(&request))
+        else {
+            return Some((request, channels));
+        };
+        let returned = match handle.shared.handoff.lock().as_ref() {
+            Some(sessions) => sessions.send((request, channels)).err().map(|err| err.0),
+            None => Some((request, channels)),
+        };
+        if returned.is_some() {
+            // The warm mic closed itself, e.g. after its idle timeout
+            *warm = None;
+        }
+        returned
+    }
+
+    pub fn next_session_id(&self) -> u64 {
+        self.session_counter.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn take_session(&self) -> Option<SessionSlot> {
         self.session.lock().take()
     }
+
+    /// Id of the session attached to the running capture, if any.
+    pub fn session_id(&self) -> Option<u64> {
+        self.session.lock().as_ref().map(|(_, info)| info.id)
+    }
 }
 
-fn capture_loop(
-    preferred: Option<String>,
-    desired_sample_rate: u32,
-    tx: mpsc::Sender<Vec<i16>>,
-    stop: Arc<AtomicBool>,
-    ready: std_mpsc::Sender<AppResult<u32>>,
-) -> AppResult<()> {
-    let host = cpal::default_host();
-    let device = select_input_device(&host, preferred)?;
-    let (supported, sample_rate) = resolve_stream_config(&device, desired_sample_rate)?;
-    let config: StreamConfig = supported.clone().into();
+/// An opened input stream and the ring its callback feeds.
+struct ActiveStream {
+    /// Capture stops when this is dropped.
+    _stream: Stream,
+    ring: Arc<CaptureRing>,
+    /// Set by the stream's error callback when the device disappears.
+    lost: Arc<AtomicBool>,
+    sample_rate: u32,
+    device_name: Option<String>,
+    device_id: Option<String>,
+}
 
-    let (frame_tx, frame_rx) = std_mpsc::channel::<Vec<i16>>();
-    let err_fn = |err| error!(%err, "audio stream error");
-    let stream = match supported.sample_format() {
-        SampleFormat::F32 => build_stream::<f32>(&device, &config, frame_tx.clone(), err_fn),
-        SampleFormat::I16 => build_stream::<i16>(&device, &config, frame_tx.clone(), err_fn),
-        SampleFormat::U16 => build_stream::<u16>(&device, &config, frame_tx.clone(), err_fn),
-        other => Err(AppError::AudioInit(format!(
-            "unsupported sample format: {other:?}"
-        ))),
-    };
+/// Resamples, converts and forwards captured audio to the backend in frames
+/// of `FRAME_DURATION`.
+struct Pipeline {
+    output_rate: u32,
+    resampler: Resampler,
+    resampled: Vec<f32>,
+    processor: VoiceProcessor,
+    /// Processed audio not yet making up a whole frame.
+    pending: Vec<f32>,
+    frame_len: usize,
+    detector: Option<(EnergyVad, VadCallback)>,
+    meter: LevelMeter,
+    on_event: CaptureCallback,
+    total_samples: usize,
+    tx: AudioSender,
+    max_loss_percent: f32,
+}
 
-    let stream = match stream {
-        Ok(stream) => {
-            if let Err(err) = stream.play() {
-                let app_err = AppError::AudioInit(err.to_string());
-                let _ = ready.send(Err(app_err.clone()));
-                return Err(app_err);
-            }
-            if let Ok(name) = device.name() {
-                info!(
-                    device = %name,
-                    channels = config.channels,
-                    sample_rate,
-                    "capturing audio input"
-                );
+impl Pipeline {
+    /// Returns `Ok(false)` once the backend has gone away, and an error when
+    /// more audio was dropped than the session tolerates.
+    fn push(&mut self, samples: &[f32]) -> AppResult<bool> {
+        self.resampled.clear();
+        self.resampler.process(samples, &mut self.resampled);
+        self.ingest();
+        self.deliver_frames(false)
+    }
+
+    /// Drains the resampler and continues at a new device rate. A partial
+    /// frame is kept so the switch does not break the frame grid.
+    fn set_input_rate(&mut self, sample_rate: u32) {
+        self.resampled.clear();
+        self.resampler.flush(&mut self.resampled);
+        self.ingest();
+        self.resampler = Resampler::new(sample_rate, self.output_rate);
+    }
+
+    /// Drains the resampler and the processing chain and sends the remaining
+    /// audio, including a shorter last frame.
+    fn finish(&mut self) -> AppResult<bool> {
+        self.resampled.clear();
+        self.resampler.flush(&mut self.resampled);
+        self.ingest();
+        self.processor.flush(&mut self.pending);
+        self.deliver_frames(true)
+    }
+
+    /// Meters the resampled input as captured, so clipping is reported even
+    /// when gain control hides it, then runs it through the processing chain.
+    fn ingest(&mut self) {
+        let on_event = &self.on_event;
+        self.meter.process(&self.resampled, |event| {
+            on_event(match event {
+                LevelEvent::Reading(reading) => CaptureEvent::Level(reading),
+                LevelEvent::Warning(warning) => {
+                    warn!(?warning, "input level warning");
+                    CaptureEvent::Warning(warning)
+                }
+            })
+        });
+        self.processor.process(&self.resampled, &mut self.pending);
+    }
+
+    fn deliver_frames(&mut self, partial: bool) -> AppResult<bool> {
+        let mut start = 0;
+        let mut open = true;
+        while open {
+            let remaining = self.pending.len() - start;
+            if remaining == 0 || (remaining < self.frame_len && !partial) {
+                break;
             }
-            let _ = ready.send(Ok(sample_rate));
-            stream
+            let end = start + remaining.min(self.frame_len);
+            let chunk: Vec<i16> = self.pending[start..end]
+                .iter()
+                .copied()
+                .map(to_pcm16)
+                .collect();
+            start = end;
+            open = self.send(chunk)?;
         }
-        Err(err) => {
-            let _ = ready.send(Err(err.clone()));
-            return Err(err);
+        self.pending.drain(..start);
+        Ok(open)
+    }
+
+    fn send(&mut self, chunk: Vec<i16>) -> AppResult<bool> {
+        self.total_samples += chunk.len();
+        if let Some((detector, on_event)) = self.detector.as_mut() {
+            let at_samples = self.total_samples;
+            detector.process(&chunk, |event| {
+                debug!(?event, at_samples, "voice activity");
+                on_event(event);
+            });
+        }
+        let Some(dropped) = self.tx.push(chunk) else {
+            return Ok(false);
+        };
+        if dropped == 0 {
+            return Ok(true);
         }
+        let stats = self.tx.stats();
+        if stats.dropped_samples == dropped as u64 {
+            warn!("backend is falling behind, dropping the oldest queued audio");
+        }
+        let loss_percent = stats.dropped_samples as f32 * 100.0 / stats.pushed_samples as f32;
+        if loss_percent > self.max_loss_percent {
+            return Err(AppError::AudioOverrun(format!(
+                "{:.1} s ({loss_percent:.1}%) of the recording could not be sent in time",
+                stats.dropped_samples as f64 / f64::from(self.output_rate)
+            )));
+        }
+        Ok(true)
+    }
+
+    fn stats(&self) -> CaptureStats {
+        let stats = self.tx.stats();
+        let to_ms = |samples: u64| samples * 1000 / u64::from(self.output_rate.max(1));
+        CaptureStats {
+            dropped_ms: to_ms(stats.dropped_samples),
+            peak_backlog_ms: to_ms(stats.peak_queued_samples as u64),
+            levels: self.meter.stats(),
+        }
+    }
+}
+
+/// Runs a session on a stream of its own, reporting a failure to open it
+/// back to `RecorderService::start`.
+fn run_cold(request: RecorderRequest, channels: SessionChannels) {
+    let ready = channels.ready.clone();
+    let stop = channels.stop.clone();
+    if let Err(err) = capture_loop(request, channels) {
+        error!(error = %err, "audio capture failed");
+        let _ = ready.send(Err(err));
+        stop.store(true, Ordering::SeqCst);
+    }
+}
+
+fn capture_loop(request: RecorderRequest, channels: SessionChannels) -> AppResult<()> {
+    let host = cpal::default_host();
+    let selected = select_input_device(&host, request.input_device.as_deref())?;
+    let mut active = open_capture(
+        &selected,
+        request.output_rate,
+        request.sample_rate,
+        request.channel.map(usize::from),
+    )?;
+    run_session(&host, request, channels, &mut active, selected.missing, &[]);
+    Ok(())
+}
+
+/// Runs one session on an open stream, starting with `preroll` (device-rate
+/// samples captured before the session).
+fn run_session(
+    host: &cpal::Host,
+    mut request: RecorderRequest,
+    channels: SessionChannels,
+    active: &mut ActiveStream,
+    missing_device: Option<String>,
+    preroll: &[f32],
+) {
+    let output_rate = request.output_rate;
+    let _ = channels.ready.send(Ok(CaptureInfo {
+        sample_rate: output_rate,
+        device_sample_rate: active.sample_rate,
+        device_name: active.device_name.clone(),
+        device_id: active.device_id.clone(),
+        missing_device,
+    }));
+
+    let mut pipeline = Pipeline {
+        output_rate,
+        resampler: Resampler::new(active.sample_rate, output_rate),
+        resampled: Vec::new(),
+        processor: VoiceProcessor::new(output_rate, &request.processing),
+        pending: Vec::new(),
+        frame_len: ((FRAME_DURATION.as_secs_f64() * f64::from(output_rate)) as usize).max(1),
+        detector: request.vad.take().map(|vad| {
+            let detector = EnergyVad::new(output_rate, vad.trailing_silence_ms, vad.margin_db);
+            (detector, vad.on_event)
+        }),
+        meter: LevelMeter::new(output_rate),
+        on_event: request.on_event.clone(),
+        total_samples: 0,
+        tx: channels.tx,
+        max_loss_percent: request.max_loss_percent,
     };
+    let channel = request.channel.map(usize::from);
+    let result = pipeline
+        .push(preroll)
+        .and_then(|_| {
+            pump(
+                host,
+                &request,
+                channel,
+                &channels.stop,
+                active,
+                &mut pipeline,
+            )
+        })
+        .and_then(|()| pipeline.finish().map(drop));
+
+    let stats = pipeline.stats();
+    debug!(
+        total_samples = pipeline.total_samples,
+        preroll_samples = preroll.len(),
+        dropped_ms = stats.dropped_ms,
+        "audio capture loop stopping"
+    );
+    // Closes the queue, so the backend finishes with what it has
+    drop(pipeline);
+    let mut report = channels.report.lock();
+    report.stats = stats;
+    if let Err(err) = result {
+        error!(error = %err, "audio capture ended early");
+        report.error = Some(err.clone());
+        drop(report);
+        (request.on_event)(CaptureEvent::Failed(err));
+    }
+}
 
-    let mut total_samples = 0usize;
+/// Moves audio from the stream into the pipeline until stopped, switching to
+/// the default device when the current one goes away.
+fn pump(
+    host: &cpal::Host,
+    request: &RecorderRequest,
+    channel: Option<usize>,
+    stop: &AtomicBool,
+    active: &mut ActiveStream,
+    pipeline: &mut Pipeline,
+) -> AppResult<()> {
+    let mut last_frames = Instant::now();
+    let mut failovers = 0;
+    let mut samples = Vec::new();
     while !stop.load(Ordering::SeqCst) {
-        match frame_rx.recv_timeout(Duration::from_millis(200)) {
-            Ok(chunk) if !chunk.is_empty() => {
-                total_samples += chunk.len();
-                if let Err(err) = tx.try_send(chunk) {
-                    match err {
-                        TrySendError::Full(_) => {
-                            warn!("audio channel full, dropping samples");
-                        }
-                        TrySendError::Closed(_) => {
-                            break;
-                        }
-                    }
-                }
+        if active.lost.load(Ordering::SeqCst) || last_frames.elapsed() >= STALL_TIMEOUT {
+            let lost_device = active.device_name.clone();
+            warn!(device = ?lost_device, "audio input device lost");
+            // Keep whatever the device delivered before it went away
+            samples.clear();
+            active.ring.drain_into(&mut samples);
+            report_overflow(active);
+            if !pipeline.push(&samples)? {
+                return Ok(());
             }
-            Ok(_) => continue,
-            Err(std_mpsc::RecvTimeoutError::Timeout) => continue,
-            Err(_) => break,
+            let next = fail_over(host, request, channel, &mut failovers).map_err(|err| {
+                AppError::AudioDeviceLost(match &lost_device {
+                    Some(name) => format!("{name} ({err})"),
+                    None => err.to_string(),
+                })
+            })?;
+            pipeline.set_input_rate(next.sample_rate);
+            (request.on_event)(CaptureEvent::DeviceSwitched(DeviceSwitch {
+                lost_device,
+                device_id: next.device_id.clone(),
+                device_name: next.device_name.clone(),
+            }));
+            *active = next;
+            last_frames = Instant::now();
+            continue;
+        }
+
+        samples.clear();
+        active.ring.drain_into(&mut samples);
+        if samples.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        last_frames = Instant::now();
+        if !pipeline.push(&samples)? {
+            return Ok(());
         }
     }
 
-    debug!(total_samples, "audio capture loop stopping");
-    drop(stream);
+    samples.clear();
+    active.ring.drain_into(&mut samples);
+    report_overflow(active);
+    pipeline.push(&samples)?;
     Ok(())
 }
 
-fn select_input_device(host: &cpal::Host, preferred: Option<String>) -> AppResult<Device> {
-    if let Some(name) = preferred.and_then(|s| {
-        let trimmed = s.trim().to_string();
-        if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed)
+/// Holds the stream open between sessions, keeping the last `preroll` of
+/// audio in memory, and runs sessions handed over by `RecorderService::start`.
+/// Exits when disarmed, after `idle_timeout` without a session, or when the
+/// device goes away while idle.
+fn warm_loop(
+    request: WarmRequest,
+    sessions: std_mpsc::Receiver<(RecorderRequest, SessionChannels)>,
+    ready: std_mpsc::Sender<AppResult<()>>,
+    on_mic: MicCallback,
+    shared: Arc<WarmShared>,
+) {
+    let host = cpal::default_host();
+    let opened = select_input_device(&host, request.input_device.as_deref()).and_then(|selected| {
+        let active = open_capture(
+            &selected,
+            request.output_rate,
+            request.sample_rate,
+            request.channel.map(usize::from),
+        )?;
+        Ok((active, selected.missing))
+    });
+    let (mut active, missing_device) = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            shared.handoff.lock().take();
+            let _ = ready.send(Err(err));
+            return;
         }
-    }) {
-        for device in host
-            .input_devices()
-            .map_err(|err| AppError::AudioDevice(err.to_string()))?
-        {
-            if device.name().map(|n| n == name).unwrap_or(false) {
-                return Ok(device);
+    };
+    shared.device_id.lock().clone_from(&active.device_id);
+    shared
+        .on_fallback
+        .store(missing_device.is_some(), Ordering::SeqCst);
+    let _ = ready.send(Ok(()));
+    info!(device = ?active.device_name, preroll = ?request.preroll, "warm mic armed");
+    on_mic(MicState {
+        open: true,
+        device_name: active.device_name.clone(),
+    });
+
+    let mut preroll: VecDeque<f32> = VecDeque::new();
+    let mut samples = Vec::new();
+    let mut idle_since = Instant::now();
+    let mut last_frames = Instant::now();
+    loop {
+        match sessions.try_recv() {
+            Ok((session, channels)) => {
+                let mut buffered: Vec<f32> = preroll.drain(..).collect();
+                active.ring.drain_into(&mut buffered);
+                // The session ends once the device it ended on is recorded,
+                // so that re-arming after it sees a failover
+                let done = channels.done.clone();
+                run_session(
+                    &host,
+                    session,
+                    channels,
+                    &mut active,
+                    missing_device.clone(),
+                    &buffered,
+                );
+                shared.record_device(&active);
+                drop(done);
+                idle_since = Instant::now();
+                last_frames = Instant::now();
+                continue;
             }
+            Err(std_mpsc::TryRecvError::Empty) => {}
+            Err(std_mpsc::TryRecvError::Disconnected) => break,
         }
-        warn!(device = %name, "preferred audio input device not found, falling back to default");
+        if idle_since.elapsed() >= request.idle_timeout {
+            info!("warm mic idle, closing the microphone");
+            break;
+        }
+        if active.lost.load(Ordering::SeqCst) || last_frames.elapsed() >= STALL_TIMEOUT {
+            warn!(device = ?active.device_name, "warm mic device lost");
+            break;
+        }
+
+        samples.clear();
+        active.ring.drain_into(&mut samples);
+        if samples.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        last_frames = Instant::now();
+        // The rate can change when a session failed over to another device
+        let capacity = (request.preroll.as_secs_f64() * f64::from(active.sample_rate)) as usize;
+        let keep = &samples[samples.len().saturating_sub(capacity)..];
+        let excess = (preroll.len() + keep.len()).saturating_sub(capacity);
+        preroll.drain(..excess.min(preroll.len()));
+        preroll.extend(keep);
     }
 
-    host.default_input_device()
-        .ok_or_else(|| AppError::AudioDevice("no audio input device available".into()))
+    shared.handoff.lock().take();
+    preroll.clear();
+    drop(active);
+    on_mic(MicState {
+        open: false,
+        device_name: None,
+    });
+    // Handed over after the last check; runs on its own stream now that the
+    // device is released
+    for (session, channels) in sessions.try_iter() {
+        run_cold(session, channels);
+    }
 }
 
+fn report_overflow(active: &ActiveStream) {
+    let overflowed = active.ring.overflowed();
+    if overflowed > 0 {
+        warn!(
+            device = ?active.device_name,
+            overflowed_ms = overflowed * 1000 / u64::from(active.sample_rate.max(1)),
+            "capture ring overflowed, the bridge thread fell behind"
+        );
+    }
+}
+
+/// Reopens capture on the current default device after a loss.
+fn fail_over(
+    host: &cpal::Host,
+    request: &RecorderRequest,
+    channel: Option<usize>,
+    failovers: &mut u32,
+) -> AppResult<ActiveStream> {
+    if *failovers >= MAX_FAILOVERS {
+        return Err(AppError::AudioDevice(format!(
+            "gave up after {MAX_FAILOVERS} device switches"
+        )));
+    }
+    *failovers += 1;
+    thread::sleep(FAILOVER_DELAY);
+    let selected = select_input_device(host, None)?;
+    let active = open_capture(&selected, request.output_rate, request.sample_rate, channel)?;
+    info!(device = ?active.device_name, "switched audio input to default device");
+    Ok(active)
+}
+
+fn open_capture(
+    selected: &SelectedDevice,
+    output_rate: u32,
+    desired_sample_rate: u32,
+    channel: Option<usize>,
+) -> AppResult<ActiveStream> {
+    let device = &selected.device;
+    // Opening the device at the output rate avoids resampling altogether
+    let (supported, sample_rate) =
+        resolve_stream_config(device, &[output_rate, desired_sample_rate])?;
+    let config: StreamConfig = supported.clone().into();
+
+    let channels = usize::from(config.channels);
+    let channel = match channel {
+        Some(index) if index >= channels => {
+            warn!(
+                channel = index,
+                channels, "selected input channel not available, averaging all channels"
+            );
+            None
+        }
+        other => other,
+    };
+
+    let ring = Arc::new(CaptureRing::new(
+        (RING_DURATION.as_secs_f64() * f64::from(sample_rate)) as usize,
+    ));
+    let lost = Arc::new(AtomicBool::new(false));
+    let stream = open_stream(
+        device,
+        &config,
+        supported.sample_format(),
+        channel,
+        ring.clone(),
+        lost.clone(),
+    )?;
+    stream
+        .play()
+        .map_err(|err| AppError::AudioInit(err.to_string()))?;
+
+    let device_name = device.name().ok();
+    if let Some(name) = &device_name {
+        info!(
+            device = %name,
+            channels = config.channels,
+            ?channel,
+            sample_rate,
+            output_rate,
+            "capturing audio input"
+        );
+    }
+    Ok(ActiveStream {
+        _stream: stream,
+        ring,
+        lost,
+        sample_rate,
+        device_name,
+        device_id: selected.id.clone(),
+    })
+}
+
+/// Builds the input stream for whatever sample format the device exposes.
+/// Every format is converted to `f32` in the callback, so the rest of the
+/// pipeline is format-agnostic.
+fn open_stream(
+    device: &Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    channel: Option<usize>,
+    ring: Arc<CaptureRing>,
+    lost: Arc<AtomicBool>,
+) -> AppResult<Stream> {
+    let err_fn = move |err| {
+        error!(%err, "audio stream error");
+        if matches!(err, StreamError::DeviceNotAvailable) {
+            lost.store(true, Ordering::SeqCst);
+        }
+    };
+    match format {
+        SampleFormat::I8 => build_stream::<i8>(device, config, channel, ring, err_fn),
+        SampleFormat::I16 => build_stream::<i16>(device, config, channel, ring, err_fn),
+        SampleFormat::I24 => build_stream::<I24>(device, config, channel, ring, err_fn),
+        SampleFormat::I32 => build_stream::<i32>(device, config, channel, ring, err_fn),
+        SampleFormat::I64 => build_stream::<i64>(device, config, channel, ring, err_fn),
+        SampleFormat::U8 => build_stream::<u8>(device, config, channel, ring, err_fn),
+        SampleFormat::U16 => build_stream::<u16>(device, config, channel, ring, err_fn),
+        SampleFormat::U32 => build_stream::<u32>(device, config, channel, ring, err_fn),
+        SampleFormat::U64 => build_stream::<u64>(device, config, channel, ring, err_fn),
+        SampleFormat::F32 => build_stream::<f32>(device, config, channel, ring, err_fn),
+        SampleFormat::F64 => build_stream::<f64>(device, config, channel, ring, err_fn),
+        // `SampleFormat` is non-exhaustive; formats added by later cpal releases
+        other => Err(AppError::AudioInit(format!(
+            "unsupported sample format: {other:?}"
+        ))),
+    }
+}
+
+/// Picks the first of `rates` the device supports, else its default config.
+/// Whatever the result, the capture loop resamples to the output rate.
 fn resolve_stream_config(
     device: &Device,
-    desired_sample_rate: u32,
+    rates: &[u32],
 ) -> AppResult<(SupportedStreamConfig, u32)> {
-    let mut chosen: Option<SupportedStreamConfig> = None;
     if let Ok(configs) = device.supported_input_configs() {
-        for config in configs {
-            let min = config.min_sample_rate().0;
-            let max = config.max_sample_rate().0;
-            if desired_sample_rate >= min && desired_sample_rate <= max {
-                let with_rate = config.with_sample_rate(cpal::SampleRate(desired_sample_rate));
-                chosen = Some(with_rate);
-                break;
+        let configs: Vec<_> = configs.collect();
+        for &rate in rates {
+            let matching = configs.iter().find(|config| {
+                (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&rate)
+            });
+            if let Some(config) = matching {
+                return Ok((config.with_sample_rate(cpal::SampleRate(rate)), rate));
             }
         }
     }
 
-    if chosen.is_none() {
-        let default = device
-            .default_input_config()
-            .map_err(|err| AppError::AudioDevice(err.to_string()))?;
-        let fallback_rate = default.sample_rate().0;
-        chosen = Some(default);
-        warn!(
-            desired_sample_rate,
-            fallback = fallback_rate,
-            "falling back to device sample rate"
-        );
-    }
-
-    let supported = chosen.expect("sample rate resolution failed");
-    Ok((supported.clone(), supported.sample_rate().0))
+    let default = device
+        .default_input_config()
+        .map_err(|err| AppError::AudioDevice(err.to_string()))?;
+    let fallback_rate = default.sample_rate().0;
+    warn!(
+        ?rates,
+        fallback = fallback_rate,
+        "requested sample rates unsupported, resampling from device default"
+    );
+    Ok((default, fallback_rate))
 }
 
 fn build_stream<T>(
     device: &Device,
     config: &StreamConfig,
-    tx: std_mpsc::Sender<Vec<i16>>,
-    err_fn: impl Fn(cpal::StreamError) + Send + 'static,
+    channel: Option<usize>,
+    ring: Arc<CaptureRing>,
+    err_fn: impl FnMut(StreamError) + Send + 'static,
 ) -> AppResult<Stream>
 where
-    T: Sample + SizedSample + Into<f32>,
+    T: SizedSample + FullScale,
+    f32: FromSample<T>,
 {
     let channels = config.channels as usize;
     device
         .build_input_stream(
             config,
-            move |data: &[T], _| {
-                let mut chunk = Vec::with_capacity(data.len() / channels);
-                for frame in data.chunks(channels) {
-                    let value: f32 = frame[0].into();
-                    let clamped = (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                    chunk.push(clamped);
-                }
-                let _ = tx.send(chunk);
-            },
+            move |data: &[T], _| ring.push_frames(data, channels, channel),
             err_fn,
             None,
         )
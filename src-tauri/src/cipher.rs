@@ -0,0 +1,77 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::errors::{AppError, AppResult};
+
+/// Format version of an encrypted column value. Whether a row is encrypted is
+/// recorded in its `encrypted` column; the prefix is never used to guess it.
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// ChaCha20-Poly1305 for individual column values, stored as
+/// `enc:v1:<base64(nonce || ciphertext)>`.
+pub struct FieldCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl FieldCipher {
+    /// Returns a fresh cipher and its key encoded for the secret store.
+    pub fn generate() -> (Self, String) {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let encoded = BASE64.encode(key.as_slice());
+        (Self::from_key(&key), encoded)
+    }
+
+    pub fn from_encoded(encoded: &str) -> AppResult<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|err| AppError::History(format!("invalid history key: {err}")))?;
+        if bytes.len() != 32 {
+            return Err(AppError::History("invalid history key length".into()));
+        }
+        Ok(Self::from_key(Key::from_slice(&bytes)))
+    }
+
+    fn from_key(key: &Key) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(key),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> AppResult<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| AppError::History("unable to encrypt history entry".into()))?;
+        let mut body = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        body.extend_from_slice(nonce.as_slice());
+        body.extend_from_slice(&ciphertext);
+        Ok(format!("{PREFIX}{}", BASE64.encode(body)))
+    }
+
+    /// Decrypts a value written by `encrypt`.
+    pub fn decrypt(&self, stored: &str) -> AppResult<String> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Err(AppError::History(
+                "encrypted history entry has an unknown format".into(),
+            ));
+        };
+        let raw = BASE64
+            .decode(encoded)
+            .map_err(|err| AppError::History(err.to_string()))?;
+        if raw.len() < NONCE_LEN {
+            return Err(AppError::History(
+                "encrypted history entry is truncated".into(),
+            ));
+        }
+        let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| AppError::History("unable to decrypt history entry".into()))?;
+        String::from_utf8(plaintext).map_err(|err| AppError::History(err.to_string()))
+    }
+}
@@ -1,38 +1,209 @@
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use serde::Serialize;
-use tauri::{AppHandle, State};
-use tracing::info;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_dialog::{DialogExt, FilePath};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
 
-use crate::audio::RecorderRequest;
+use crate::audio::{
+    CaptureCallback, CaptureEvent, CaptureStats, DeviceSwitch, LocalVad, MicState, RecorderRequest,
+    RecorderService, SessionInfo, WarmRequest,
+};
+use crate::devices::{self, InputDeviceInfo};
 use crate::errors::{AppError, CommandError, CommandResult};
-use crate::history::HistoryEntry;
-use crate::paste::PasteOutcome;
-use crate::realtime;
-use crate::settings::{AppSettings, DEFAULT_REALTIME_MODEL};
+use crate::export::{self, ExportFormat, ImportReport};
+use crate::history::{HistoryEntry, HistoryPage, HistoryQuery, NewHistoryEntry};
+use crate::meter::{LevelReading, LevelWarning};
+use crate::paste::{focused_app_name, PasteOutcome};
+use crate::settings::{
+    AppSettings, SettingsView, TranscriptionEngine, VadMode, DEFAULT_REALTIME_MODEL,
+};
 use crate::state::AppState;
+use crate::transcription::{self, SessionContext, TranscriptSink, TranscriptUpdate};
+use crate::vad::{VadCallback, VadEvent};
+
+const RECORDING_FAILED_EVENT: &str = "recording_failed";
+const TRANSCRIPT_UPDATE_EVENT: &str = "transcript_update";
+/// The configured input device is gone and the system default is recording.
+const INPUT_DEVICE_MISSING_EVENT: &str = "input_device_missing";
+/// The input device went away mid-session and the default device took over.
+const INPUT_DEVICE_SWITCHED_EVENT: &str = "input_device_switched";
+/// The warm mic opened or closed the microphone outside of a session.
+const MIC_OPEN_EVENT: &str = "mic_open";
+/// Throttled input level during a session, for the VU meter.
+const INPUT_LEVEL_EVENT: &str = "input_level";
+/// Sustained silence, clipping or DC offset in the input.
+const INPUT_WARNING_EVENT: &str = "input_warning";
+const VAD_ACTIVITY_EVENT: &str = "vad_activity";
+/// Trailing silence ended the utterance. The backend stops the session itself;
+/// the event only tells the frontend why the recording ended.
+const VAD_AUTO_STOP_EVENT: &str = "vad_auto_stop";
+
+#[derive(Debug, Clone, Serialize)]
+struct VadActivity {
+    session_id: u64,
+    speaking: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InputDeviceMissing {
+    requested: String,
+    fallback_id: Option<String>,
+    fallback_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InputDeviceSwitched {
+    session_id: u64,
+    #[serde(flatten)]
+    switch: DeviceSwitch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InputLevel {
+    session_id: u64,
+    #[serde(flatten)]
+    reading: LevelReading,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InputWarning {
+    session_id: u64,
+    warning: LevelWarning,
+}
 
 #[derive(Debug, Serialize)]
 pub struct RecordingSummary {
     pub text: String,
     pub pasted: bool,
     pub duration_ms: i64,
+    /// Why capture ended before the stop, e.g. `AUDIO_DEVICE_LOST`; `text`
+    /// then holds what was transcribed up to that point.
+    pub error: Option<CommandError>,
+    pub audio: CaptureStats,
 }
 
 #[tauri::command]
-pub async fn start_recording(state: State<'_, AppState>) -> CommandResult<()> {
-    let settings = state.settings.get().await;
-    if settings.api_key.trim().is_empty() {
-        return Err(AppError::MissingApiKey.into());
+pub async fn start_recording(app: AppHandle, state: State<'_, AppState>) -> CommandResult<()> {
+    begin_recording(&app, &state).await
+}
+
+#[tauri::command]
+pub async fn stop_recording(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> CommandResult<RecordingSummary> {
+    finish_recording(&app, &state).await
+}
+
+/// Starts a push-to-talk session from the hotkey handler. Failures reach the
+/// frontend as `recording_failed`.
+pub async fn push_to_talk_start(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    if let Err(err) = begin_recording(app, &state).await {
+        warn!(error = %err.message, "push-to-talk failed to start recording");
+        let _ = app.emit(RECORDING_FAILED_EVENT, err);
+    }
+}
+
+/// Stops the push-to-talk session and pastes its transcript, as
+/// `stop_recording` does for the frontend.
+pub async fn push_to_talk_stop(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    if !state.recorder.is_recording() {
+        // The start failed and was already reported
+        return;
+    }
+    match finish_recording(app, &state).await {
+        Ok(summary) => debug!(pasted = summary.pasted, "push-to-talk session finished"),
+        Err(err) if already_stopped(&err) => debug!("push-to-talk session already stopped"),
+        Err(err) => {
+            warn!(error = %err.message, "push-to-talk session failed");
+            let _ = app.emit(RECORDING_FAILED_EVENT, err);
+        }
+    }
+}
+
+/// Ends a session from the backend, e.g. once VAD reports the end of the
+/// utterance, the transcription failed or capture ended early. The transcript
+/// is pasted and saved as `stop_recording` does, and failures, including a
+/// lost device that still left a partial transcript, are recorded in the
+/// history and reported as `recording_failed`.
+async fn end_session(app: AppHandle, session_id: u64) {
+    let state = app.state::<AppState>();
+    if state.recorder.session_id() != Some(session_id) {
+        // Already stopped by hand, or a later session is running
+        return;
+    }
+    match finish_recording(&app, &state).await {
+        Ok(RecordingSummary {
+            error: Some(err), ..
+        }) => {
+            warn!(session_id, error = %err.message, "session ended early");
+            let _ = app.emit(RECORDING_FAILED_EVENT, err);
+        }
+        Ok(summary) => debug!(session_id, pasted = summary.pasted, "session ended"),
+        Err(err) if already_stopped(&err) => debug!(session_id, "session already stopped"),
+        Err(err) => {
+            warn!(session_id, error = %err.message, "session failed");
+            let _ = app.emit(RECORDING_FAILED_EVENT, err);
+        }
     }
+}
+
+/// A concurrent stop, e.g. a manual stop racing VAD's auto-stop, ended the
+/// session between the check and `finish_recording`.
+fn already_stopped(err: &CommandError) -> bool {
+    err.code == AppError::RecorderNotRunning.code()
+}
+
+async fn begin_recording(app: &AppHandle, state: &AppState) -> CommandResult<()> {
+    let settings = state.settings.get().await;
+    let backend = transcription::backend_for(&settings).map_err(CommandError::from)?;
 
-    let sample_rate = state
+    let source_app = focused_app_name();
+    let session_id = state.recorder.next_session_id();
+    let vad_events = vad_callback(app, session_id);
+    // Without a realtime server, server turn detection runs locally instead
+    let server_vad =
+        settings.vad.mode == VadMode::Server && settings.engine == TranscriptionEngine::Realtime;
+    let local_vad = match settings.vad.mode {
+        VadMode::Off => false,
+        VadMode::Local => true,
+        VadMode::Server => !server_vad,
+    };
+    let capture = state
         .recorder
         .start(RecorderRequest {
             sample_rate: settings.sample_rate,
+            output_rate: backend.sample_rate(),
+            channel: settings.input_channel,
             input_device: settings.input_device.clone(),
+            buffer: Duration::from_secs(settings.audio_buffer.buffer_seconds.into()),
+            max_loss_percent: settings.audio_buffer.max_loss_percent,
+            processing: settings.processing,
+            vad: local_vad.then(|| LocalVad {
+                trailing_silence_ms: settings.vad.trailing_silence_ms,
+                margin_db: settings.vad.speech_margin_db,
+                on_event: vad_events.clone(),
+            }),
+            on_event: capture_callback(app, session_id),
         })
         .map_err(CommandError::from)?;
+    let sample_rate = capture.sample_rate;
+    if let Some(requested) = capture.missing_device.clone() {
+        let _ = app.emit(
+            INPUT_DEVICE_MISSING_EVENT,
+            InputDeviceMissing {
+                requested,
+                fallback_id: capture.device_id.clone(),
+                fallback_name: capture.device_name.clone(),
+            },
+        );
+    }
 
     let audio_rx = state
         .recorder
@@ -40,46 +211,109 @@ pub async fn start_recording(state: State<'_, AppState>) -> CommandResult<()> {
         .ok_or(AppError::AudioStreamUnavailable)
         .map_err(CommandError::from)?;
 
-    let api_key = settings.api_key.clone();
-    let model = settings.model.clone();
+    let sink_app = app.clone();
+    let sink: TranscriptSink = Arc::new(move |update: TranscriptUpdate| {
+        let _ = sink_app.emit(TRANSCRIPT_UPDATE_EVENT, update);
+    });
+    let recorder = state.recorder.clone();
+    let app = app.clone();
+    info!(
+        backend = backend.name(),
+        session_id, "starting transcription session"
+    );
+    let ctx = SessionContext {
+        sample_rate,
+        session_id,
+        sink,
+        turn_detection: server_vad.then(|| settings.vad.clone()),
+        vad_events,
+    };
+    let (attached_tx, attached_rx) = oneshot::channel::<()>();
     let handle = tokio::spawn(async move {
-        realtime::stream_transcription(api_key, model, sample_rate, audio_rx).await
+        let result = backend.transcribe(audio_rx, ctx).await;
+        if result.is_err() && recorder.is_recording() {
+            recorder.abort_capture();
+            // `end_session` awaits this task, so it runs separately, and only
+            // once the session it ends has been attached
+            if attached_rx.await.is_ok() {
+                tauri::async_runtime::spawn(end_session(app, session_id));
+            }
+        }
+        result
     });
 
+    let info = SessionInfo {
+        id: session_id,
+        model: model_label(&settings),
+        language: session_language(&settings),
+        sample_rate: capture.device_sample_rate,
+        input_device: capture.device_name,
+        source_app,
+    };
     state
         .recorder
-        .attach_session(handle)
+        .attach_session(handle, info)
         .map_err(CommandError::from)?;
+    let _ = attached_tx.send(());
 
     Ok(())
 }
 
-#[tauri::command]
-pub async fn stop_recording(state: State<'_, AppState>) -> CommandResult<RecordingSummary> {
-    let duration: Duration = state
+async fn finish_recording(app: &AppHandle, state: &AppState) -> CommandResult<RecordingSummary> {
+    let capture = state
         .recorder
         .stop()
         .await
         .map_err(CommandError::from)?
         .ok_or(AppError::RecorderNotRunning)
         .map_err(CommandError::from)?;
+    let duration: Duration = capture.duration;
 
-    let handle = state
+    let (handle, session) = state
         .recorder
         .take_session()
         .ok_or(AppError::RecorderNotRunning)
         .map_err(CommandError::from)?;
-    let mut settings = state.settings.get().await;
+    let settings = state.settings.get().await;
+    if settings.warm_mic.enabled {
+        // Re-open the warm mic if it closed itself while idle, or move it
+        // back to the configured device after the session failed over
+        let app = app.clone();
+        let recorder = state.recorder.clone();
+        let warm_settings = settings.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            sync_warm_mic(&app, &recorder, &warm_settings)
+        });
+    }
+    let duration_ms = duration.as_millis() as i64;
+    let session_id = session.id;
+    let mut entry = NewHistoryEntry {
+        duration_ms: Some(duration_ms),
+        model: Some(session.model),
+        language: session.language,
+        sample_rate: Some(session.sample_rate),
+        input_device: session.input_device,
+        source_app: session.source_app,
+        ..Default::default()
+    };
 
     let transcript = match handle.await {
         Ok(Ok(text)) => text,
         Ok(Err(err)) => {
+            // Losing the device before any audio arrived is the real cause
+            let err = match (err, capture.error) {
+                (AppError::AudioEmpty, Some(device_err)) => device_err,
+                (err, _) => err,
+            };
+            record_failure(state, &settings, entry, &err).await;
             let err_message = err.to_string();
-            if is_model_error(&err_message) && settings.model != DEFAULT_REALTIME_MODEL {
-                settings.model = DEFAULT_REALTIME_MODEL.into();
+            if settings.engine == TranscriptionEngine::Realtime
+                && is_model_error(&err_message)
+                && settings.model != DEFAULT_REALTIME_MODEL
+            {
                 state
                     .settings
-                    .update(settings.clone())
+                    .modify(|settings| settings.model = DEFAULT_REALTIME_MODEL.into())
                     .await
                     .map_err(CommandError::from)?;
                 return Err(AppError::Validation(format!(
@@ -89,39 +323,59 @@ pub async fn stop_recording(state: State<'_, AppState>) -> CommandResult<Recordi
             }
             return Err(err.into());
         }
-        Err(err) => return Err(AppError::Internal(err.to_string()).into()),
+        Err(err) => {
+            let err = AppError::Internal(err.to_string());
+            record_failure(state, &settings, entry, &err).await;
+            return Err(err.into());
+        }
     };
 
-    let pasted = if transcript.trim().is_empty() {
-        false
+    // A backlog overrun leaves gaps all through the text, unlike a lost device
+    if let Some(err @ AppError::AudioOverrun(_)) = &capture.error {
+        entry.text = transcript;
+        record_failure(state, &settings, entry, err).await;
+        return Err(err.clone().into());
+    }
+
+    let paste_result = if transcript.trim().is_empty() {
+        Ok(None)
     } else {
-        matches!(
-            state
-                .paste
-                .apply(&transcript, settings.auto_paste)
-                .map_err(CommandError::from)?,
-            PasteOutcome::SimulatedPaste
-        )
+        state
+            .paste
+            .apply(&transcript, settings.auto_paste)
+            .map(Some)
     };
+    entry.error = capture.error.as_ref().map(ToString::to_string);
+    match &paste_result {
+        Ok(outcome) => entry.paste_outcome = outcome.map(|o| o.as_str().to_string()),
+        Err(err) => entry.error = Some(err.to_string()),
+    }
 
     if settings.save_history && !transcript.trim().is_empty() {
+        entry.text = transcript.clone();
         state
             .history
-            .add(&transcript)
+            .add(&entry)
             .await
             .map_err(CommandError::from)?;
     }
 
+    let pasted = matches!(
+        paste_result.map_err(CommandError::from)?,
+        Some(PasteOutcome::SimulatedPaste)
+    );
+
     info!(
-        "Recording finished (duration={} ms, pasted={})",
-        duration.as_millis(),
-        pasted
+        session_id,
+        "Recording finished (duration={} ms, pasted={})", duration_ms, pasted
     );
 
     Ok(RecordingSummary {
         text: transcript,
         pasted,
-        duration_ms: duration.as_millis() as i64,
+        duration_ms,
+        error: capture.error.map(CommandError::from),
+        audio: capture.stats,
     })
 }
 
@@ -130,16 +384,156 @@ pub async fn recorder_status(state: State<'_, AppState>) -> CommandResult<bool>
     Ok(state.recorder.is_recording())
 }
 
+#[tauri::command]
+pub async fn warm_mic_status(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.recorder.is_warm())
+}
+
+#[tauri::command]
+pub async fn list_input_devices() -> CommandResult<Vec<InputDeviceInfo>> {
+    tokio::task::spawn_blocking(devices::list_input_devices)
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?
+        .map_err(CommandError::from)
+}
+
 #[tauri::command]
 pub async fn get_history(state: State<'_, AppState>) -> CommandResult<Vec<HistoryEntry>> {
     state.history.all().await.map_err(CommandError::from)
 }
 
+#[tauri::command]
+pub async fn search_history(
+    state: State<'_, AppState>,
+    query: HistoryQuery,
+) -> CommandResult<HistoryPage> {
+    state
+        .history
+        .search(&query)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn delete_history_entry(state: State<'_, AppState>, id: i64) -> CommandResult<()> {
+    state.history.delete(id).await.map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn update_history_text(
+    state: State<'_, AppState>,
+    id: i64,
+    text: String,
+) -> CommandResult<HistoryEntry> {
+    state
+        .history
+        .update_text(id, &text)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn set_history_pinned(
+    state: State<'_, AppState>,
+    id: i64,
+    pinned: bool,
+) -> CommandResult<HistoryEntry> {
+    state
+        .history
+        .set_pinned(id, pinned)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn set_history_tags(
+    state: State<'_, AppState>,
+    id: i64,
+    tags: Vec<String>,
+) -> CommandResult<HistoryEntry> {
+    state
+        .history
+        .set_tags(id, &tags)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn list_history_tags(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    state.history.tags().await.map_err(CommandError::from)
+}
+
+/// Exports to a file the user picks in a save dialog, so the webview cannot
+/// choose where files are written. Returns `None` if the dialog is cancelled.
+#[tauri::command]
+pub async fn export_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    format: ExportFormat,
+    query: Option<HistoryQuery>,
+) -> CommandResult<Option<usize>> {
+    let (name, extension) = format.file_type();
+    let dialog = app
+        .dialog()
+        .file()
+        .add_filter(name, &[extension])
+        .set_file_name(format!("history.{extension}"));
+    let Some(path) = pick_path(|picked| dialog.save_file(picked)).await? else {
+        return Ok(None);
+    };
+    export::export_history(&state.history, &path, format, query.unwrap_or_default())
+        .await
+        .map(Some)
+        .map_err(CommandError::from)
+}
+
+/// Imports a file the user picks in an open dialog. Returns `None` if the
+/// dialog is cancelled.
+#[tauri::command]
+pub async fn import_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> CommandResult<Option<ImportReport>> {
+    let dialog = app
+        .dialog()
+        .file()
+        .add_filter("History export", export::IMPORT_EXTENSIONS);
+    let Some(path) = pick_path(|picked| dialog.pick_file(picked)).await? else {
+        return Ok(None);
+    };
+    export::import_history(&state.history, &path)
+        .await
+        .map(Some)
+        .map_err(CommandError::from)
+}
+
 #[tauri::command]
 pub async fn clear_history(state: State<'_, AppState>) -> CommandResult<()> {
     state.history.clear().await.map_err(CommandError::from)
 }
 
+#[tauri::command]
+pub async fn get_history_encryption(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.history.is_encrypted().await)
+}
+
+#[tauri::command]
+pub async fn set_history_encryption(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> CommandResult<()> {
+    state
+        .history
+        .set_encryption(enabled)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn rotate_history_key(state: State<'_, AppState>) -> CommandResult<()> {
+    state.history.rotate_key().await.map_err(CommandError::from)
+}
+
 #[tauri::command]
 pub async fn trigger_record_event(app: AppHandle, state: State<'_, AppState>) -> CommandResult<()> {
     state.hotkeys.emit_trigger(&app);
@@ -147,8 +541,8 @@ pub async fn trigger_record_event(app: AppHandle, state: State<'_, AppState>) ->
 }
 
 #[tauri::command]
-pub async fn get_settings(state: State<'_, AppState>) -> CommandResult<AppSettings> {
-    Ok(state.settings.get().await)
+pub async fn get_settings(state: State<'_, AppState>) -> CommandResult<SettingsView> {
+    Ok(state.settings.view().await)
 }
 
 #[tauri::command]
@@ -157,6 +551,7 @@ pub async fn save_settings(
     state: State<'_, AppState>,
     settings: AppSettings,
 ) -> CommandResult<()> {
+    let previous_retention = state.settings.get().await.retention;
     state
         .settings
         .update(settings.clone())
@@ -164,11 +559,176 @@ pub async fn save_settings(
         .map_err(CommandError::from)?;
     state
         .hotkeys
-        .update(&app, settings.hotkey.clone())
+        .update(&app, settings.hotkey.clone(), settings.hotkey_mode)
         .map_err(CommandError::from)?;
+
+    let saved = state.settings.get().await;
+    // Arming waits for the device to open, so keep it off the async workers
+    let warm_app = app.clone();
+    let recorder = state.recorder.clone();
+    let warm_settings = saved.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        sync_warm_mic(&warm_app, &recorder, &warm_settings)
+    });
+    let retention = saved.retention;
+    if retention != previous_retention && !retention.is_unlimited() {
+        let history = state.history.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = history.apply_retention(&retention).await {
+                warn!(error = %err, "history retention failed");
+            }
+        });
+    }
     Ok(())
 }
 
+#[tauri::command]
+pub async fn clear_api_key(state: State<'_, AppState>) -> CommandResult<()> {
+    state
+        .settings
+        .clear_api_key()
+        .await
+        .map_err(CommandError::from)
+}
+
+fn vad_callback(app: &AppHandle, session_id: u64) -> VadCallback {
+    let app = app.clone();
+    Arc::new(move |event| {
+        let _ = match event {
+            VadEvent::SpeechStarted | VadEvent::SpeechEnded => app.emit(
+                VAD_ACTIVITY_EVENT,
+                VadActivity {
+                    session_id,
+                    speaking: event == VadEvent::SpeechStarted,
+                },
+            ),
+            VadEvent::EndOfUtterance => {
+                tauri::async_runtime::spawn(end_session(app.clone(), session_id));
+                app.emit(VAD_AUTO_STOP_EVENT, session_id)
+            }
+        };
+    })
+}
+
+/// Arms or disarms the warm mic to match the settings. A failure to open the
+/// device is logged; sessions then open it on demand.
+pub fn sync_warm_mic(app: &AppHandle, recorder: &RecorderService, settings: &AppSettings) {
+    if !settings.warm_mic.enabled {
+        recorder.disarm();
+        return;
+    }
+    let request = WarmRequest {
+        sample_rate: settings.sample_rate,
+        output_rate: transcription::engine_sample_rate(settings.engine),
+        channel: settings.input_channel,
+        input_device: settings.input_device.clone(),
+        preroll: Duration::from_millis(settings.warm_mic.preroll_ms.into()),
+        idle_timeout: Duration::from_secs(u64::from(settings.warm_mic.idle_timeout_minutes) * 60),
+    };
+    let mic_app = app.clone();
+    let on_mic = Arc::new(move |mic: MicState| {
+        let _ = mic_app.emit(MIC_OPEN_EVENT, mic);
+    });
+    if let Err(err) = recorder.arm(request, on_mic) {
+        warn!(error = %err, "failed to open the warm mic");
+    }
+}
+
+fn capture_callback(app: &AppHandle, session_id: u64) -> CaptureCallback {
+    let app = app.clone();
+    Arc::new(move |event| {
+        let _ = match event {
+            CaptureEvent::Level(reading) => app.emit(
+                INPUT_LEVEL_EVENT,
+                InputLevel {
+                    session_id,
+                    reading,
+                },
+            ),
+            CaptureEvent::Warning(warning) => app.emit(
+                INPUT_WARNING_EVENT,
+                InputWarning {
+                    session_id,
+                    warning,
+                },
+            ),
+            CaptureEvent::DeviceSwitched(switch) => app.emit(
+                INPUT_DEVICE_SWITCHED_EVENT,
+                InputDeviceSwitched { session_id, switch },
+            ),
+            CaptureEvent::Failed(err) => {
+                debug!(session_id, error = %err, "capture failed, ending session");
+                // Reported once the session has ended, with what was kept
+                tauri::async_runtime::spawn(end_session(app.clone(), session_id));
+                Ok(())
+            }
+        };
+    })
+}
+
+/// Keeps a record of failed sessions so the history shows what went wrong.
+/// Empty recordings are not worth an entry.
+async fn record_failure(
+    state: &AppState,
+    settings: &AppSettings,
+    mut entry: NewHistoryEntry,
+    err: &AppError,
+) {
+    if !settings.save_history || matches!(err, AppError::AudioEmpty) {
+        return;
+    }
+    entry.error = Some(err.to_string());
+    if let Err(history_err) = state.history.add(&entry).await {
+        warn!(error = %history_err, "failed to record failed session in history");
+    }
+}
+
+fn model_label(settings: &AppSettings) -> String {
+    match settings.engine {
+        TranscriptionEngine::Realtime => settings.model.clone(),
+        TranscriptionEngine::Local => {
+            let file = settings
+                .local_model_path
+                .as_deref()
+                .and_then(|path| std::path::Path::new(path).file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            format!("local:{file}")
+        }
+    }
+}
+
+/// Language the engine was told to transcribe in. The realtime API and Whisper's
+/// `auto` mode detect it themselves without reporting it back.
+fn session_language(settings: &AppSettings) -> Option<String> {
+    match settings.engine {
+        TranscriptionEngine::Realtime => None,
+        TranscriptionEngine::Local => settings
+            .local_language
+            .as_deref()
+            .map(str::trim)
+            .filter(|language| !language.is_empty() && *language != "auto")
+            .map(str::to_string),
+    }
+}
+
+/// Shows a file dialog through `show` and waits for the choice.
+async fn pick_path(
+    show: impl FnOnce(Box<dyn FnOnce(Option<FilePath>) + Send>),
+) -> CommandResult<Option<PathBuf>> {
+    let (tx, rx) = oneshot::channel();
+    show(Box::new(move |picked| {
+        let _ = tx.send(picked);
+    }));
+    let picked = rx
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    picked
+        .map(|path| path.into_path())
+        .transpose()
+        .map_err(|err| AppError::Validation(format!("unsupported file location: {err}")).into())
+}
+
 fn is_model_error(message: &str) -> bool {
     message.contains("not supported") || message.contains("model")
 }
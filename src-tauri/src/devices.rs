@@ -0,0 +1,404 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, Host, SupportedStreamConfig, SupportedStreamConfigRange};
+#[cfg(target_os = "linux")]
+use inotify::{Inotify, WatchMask};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+
+use crate::audio::RecorderService;
+use crate::errors::{AppError, AppResult};
+
+const DEVICES_CHANGED_EVENT: &str = "input_devices_changed";
+/// How often device names are compared where the OS sends no notifications,
+/// and how often a pending change checks whether the session has ended.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(2);
+/// ALSA creates a node per PCM here when a card appears.
+#[cfg(target_os = "linux")]
+const ALSA_DEVICE_DIR: &str = "/dev/snd";
+/// A card brings several device nodes; lets them all appear before listing.
+#[cfg(target_os = "linux")]
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Separates the host from the device name in an identifier.
+const ID_SEPARATOR: char = ':';
+/// Prefixes the ordinal that tells apart devices sharing a name.
+const DUPLICATE_MARKER: char = '#';
+
+/// An input device as shown in the settings UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDeviceInfo {
+    /// Identifier to store in `AppSettings::input_device`; only best-effort
+    /// stable, see [`device_id`].
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+    /// cpal sample format names, e.g. `i16` or `f32`.
+    pub sample_formats: Vec<String>,
+    pub sample_rates: Vec<SampleRateRange>,
+    pub channels: Vec<u16>,
+    pub default_sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SampleRateRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// Emitted when input devices are plugged in or removed, or the default changes.
+#[derive(Debug, Clone, Serialize)]
+struct InputDevicesChanged {
+    devices: Vec<InputDeviceInfo>,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+/// Outcome of resolving the configured input device.
+pub struct SelectedDevice {
+    pub device: Device,
+    pub id: Option<String>,
+    /// The configured device when it was not found and the default was used.
+    pub missing: Option<String>,
+}
+
+pub fn list_input_devices() -> AppResult<Vec<InputDeviceInfo>> {
+    let host = cpal::default_host();
+    let devices = enumerate(&host)?;
+    let default = default_position(&host, &devices);
+    Ok(devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, (id, name, device))| describe(&device, id, name, default == Some(index)))
+        .collect())
+}
+
+/// Watches the input device list on a background thread and emits
+/// `input_devices_changed` with the full list whenever it changes. The full
+/// enumeration, which opens every PCM on ALSA, only runs once a change was
+/// signalled, and waits until no session is recording.
+pub fn spawn_monitor(app: AppHandle, recorder: Arc<RecorderService>) {
+    let spawned = thread::Builder::new()
+        .name("device-monitor".into())
+        .spawn(move || {
+            let host = cpal::default_host();
+            let Some(mut watch) = DeviceWatch::new(&host) else {
+                return;
+            };
+            let mut known = list_input_devices()
+                .map(|devices| DeviceSnapshot::of(&devices))
+                .unwrap_or_default();
+            let mut pending = false;
+            loop {
+                pending |= watch.wait(&host, pending);
+                if !pending || recorder.is_recording() {
+                    continue;
+                }
+                pending = false;
+                let devices = match list_input_devices() {
+                    Ok(devices) => devices,
+                    Err(err) => {
+                        warn!(error = %err, "failed to list input devices");
+                        continue;
+                    }
+                };
+                let current = DeviceSnapshot::of(&devices);
+                if current == known {
+                    continue;
+                }
+                let added: Vec<String> = current
+                    .ids
+                    .iter()
+                    .filter(|id| !known.ids.contains(id))
+                    .cloned()
+                    .collect();
+                let removed: Vec<String> = known
+                    .ids
+                    .iter()
+                    .filter(|id| !current.ids.contains(id))
+                    .cloned()
+                    .collect();
+                info!(?added, ?removed, default = ?current.default, "input devices changed");
+                known = current;
+                let _ = app.emit(
+                    DEVICES_CHANGED_EVENT,
+                    InputDevicesChanged {
+                        devices,
+                        added,
+                        removed,
+                    },
+                );
+            }
+        });
+    if let Err(err) = spawned {
+        warn!(error = %err, "failed to start input device monitor");
+    }
+}
+
+#[derive(Default, PartialEq, Eq)]
+struct DeviceSnapshot {
+    ids: Vec<String>,
+    default: Option<String>,
+}
+
+impl DeviceSnapshot {
+    fn of(devices: &[InputDeviceInfo]) -> Self {
+        Self {
+            ids: devices.iter().map(|device| device.id.clone()).collect(),
+            default: devices
+                .iter()
+                .find(|device| device.is_default)
+                .map(|device| device.id.clone()),
+        }
+    }
+}
+
+/// Tells the monitor when the device list may have changed, without opening
+/// any device: inotify on the ALSA device nodes on Linux, elsewhere a
+/// comparison of device names, which those hosts list without opening them.
+struct DeviceWatch {
+    #[cfg(target_os = "linux")]
+    inotify: Inotify,
+    #[cfg(not(target_os = "linux"))]
+    names: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+impl DeviceWatch {
+    fn new(_host: &Host) -> Option<Self> {
+        let watched = Inotify::init().and_then(|inotify| {
+            inotify
+                .watches()
+                .add(ALSA_DEVICE_DIR, WatchMask::CREATE | WatchMask::DELETE)?;
+            Ok(inotify)
+        });
+        match watched {
+            Ok(inotify) => Some(Self { inotify }),
+            Err(err) => {
+                warn!(error = %err, "cannot watch {ALSA_DEVICE_DIR}, input devices are not monitored");
+                None
+            }
+        }
+    }
+
+    /// Blocks until device nodes come or go. While a change is already
+    /// pending it only sleeps, so the monitor can check on the session.
+    fn wait(&mut self, _host: &Host, pending: bool) -> bool {
+        let mut buffer = [0; 4096];
+        if pending {
+            thread::sleep(MONITOR_INTERVAL);
+        } else if let Err(err) = self.inotify.read_events_blocking(&mut buffer) {
+            warn!(error = %err, "failed to read device notifications");
+            thread::sleep(MONITOR_INTERVAL);
+            return false;
+        } else {
+            thread::sleep(SETTLE_TIME);
+        }
+        let mut changed = !pending;
+        // Drain what queued up meanwhile; the read fails once none is left
+        while self.inotify.read_events(&mut buffer).is_ok() {
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl DeviceWatch {
+    fn new(host: &Host) -> Option<Self> {
+        Some(Self {
+            names: device_names(host),
+        })
+    }
+
+    fn wait(&mut self, host: &Host, _pending: bool) -> bool {
+        thread::sleep(MONITOR_INTERVAL);
+        let names = device_names(host);
+        let changed = names != self.names;
+        self.names = names;
+        changed
+    }
+}
+
+/// Names of all devices and of the default input. Unlike `input_devices`,
+/// this queries no stream configurations.
+#[cfg(not(target_os = "linux"))]
+fn device_names(host: &Host) -> Vec<String> {
+    let mut names: Vec<String> = host
+        .devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default();
+    names.extend(
+        host.default_input_device()
+            .and_then(|device| device.name().ok()),
+    );
+    names
+}
+
+/// Resolves the configured device, falling back to the system default when it
+/// is unset or no longer present. Identifiers from `list_input_devices` and
+/// bare device names from older settings are both accepted.
+pub fn select_input_device(host: &Host, preferred: Option<&str>) -> AppResult<SelectedDevice> {
+    let preferred = preferred.map(str::trim).filter(|value| !value.is_empty());
+    let mut devices = enumerate(host)?;
+    if let Some(wanted) = preferred {
+        let found = devices
+            .iter()
+            .position(|(id, _, _)| id == wanted)
+            .or_else(|| devices.iter().position(|(_, name, _)| name == wanted));
+        if let Some(index) = found {
+            let (id, _, device) = devices.swap_remove(index);
+            return Ok(SelectedDevice {
+                device,
+                id: Some(id),
+                missing: None,
+            });
+        }
+        warn!(device = %wanted, "preferred audio input device not found, falling back to default");
+    }
+
+    let missing = preferred.map(str::to_string);
+    if let Some(index) = default_position(host, &devices) {
+        let (id, _, device) = devices.swap_remove(index);
+        return Ok(SelectedDevice {
+            device,
+            id: Some(id),
+            missing,
+        });
+    }
+    // A default that is not among the input devices has no identifier
+    let device = host
+        .default_input_device()
+        .ok_or_else(|| AppError::AudioDevice("no audio input device available".into()))?;
+    Ok(SelectedDevice {
+        device,
+        id: None,
+        missing,
+    })
+}
+
+/// Finds the system default among enumerated devices. cpal cannot compare
+/// devices, so when several share the default's name the one offering the
+/// same configurations is taken, and the first of them if that still ties.
+fn default_position(host: &Host, devices: &[(String, String, Device)]) -> Option<usize> {
+    let default = host.default_input_device()?;
+    let name = default.name().ok()?;
+    let candidates: Vec<usize> = devices
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, device_name, _))| *device_name == name)
+        .map(|(index, _)| index)
+        .collect();
+    if candidates.len() > 1 {
+        let signature = config_signature(&default);
+        if let Some(&index) = candidates
+            .iter()
+            .find(|&&index| config_signature(&devices[index].2) == signature)
+        {
+            return Some(index);
+        }
+    }
+    candidates.first().copied()
+}
+
+fn config_signature(
+    device: &Device,
+) -> Option<(Vec<SupportedStreamConfigRange>, SupportedStreamConfig)> {
+    let mut ranges: Vec<_> = device.supported_input_configs().ok()?.collect();
+    ranges.sort_by_key(|range| {
+        (
+            range.channels(),
+            range.min_sample_rate().0,
+            range.max_sample_rate().0,
+            range.sample_format().to_string(),
+        )
+    });
+    Some((ranges, device.default_input_config().ok()?))
+}
+
+/// Builds `host:name`, with `#n` appended for the n-th device of a name so
+/// that identical devices keep distinct identifiers.
+///
+/// Identifiers are only best-effort stable: the ordinal follows the order in
+/// which the OS enumerates devices, so two devices sharing a name can swap
+/// identifiers when that order changes, e.g. after replugging or a reboot.
+/// A device with a unique name keeps its identifier as long as the OS keeps
+/// its name.
+fn device_id(host: &Host, name: &str, ordinal: usize) -> String {
+    let mut id = format!("{}{ID_SEPARATOR}{name}", host.id().name().to_lowercase());
+    if ordinal > 1 {
+        id.push_str(&format!("{DUPLICATE_MARKER}{ordinal}"));
+    }
+    id
+}
+
+fn enumerate(host: &Host) -> AppResult<Vec<(String, String, Device)>> {
+    let mut seen: Vec<(String, usize)> = Vec::new();
+    let mut devices = Vec::new();
+    for device in host
+        .input_devices()
+        .map_err(|err| AppError::AudioDevice(err.to_string()))?
+    {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let ordinal = match seen.iter_mut().find(|(seen_name, _)| *seen_name == name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                seen.push((name.clone(), 1));
+                1
+            }
+        };
+        devices.push((device_id(host, &name, ordinal), name, device));
+    }
+    Ok(devices)
+}
+
+fn describe(device: &Device, id: String, name: String, is_default: bool) -> InputDeviceInfo {
+    let mut sample_formats = Vec::new();
+    let mut sample_rates = Vec::new();
+    let mut channels = Vec::new();
+    match device.supported_input_configs() {
+        Ok(configs) => {
+            for config in configs {
+                let format = config.sample_format().to_string();
+                if !sample_formats.contains(&format) {
+                    sample_formats.push(format);
+                }
+                let range = SampleRateRange {
+                    min: config.min_sample_rate().0,
+                    max: config.max_sample_rate().0,
+                };
+                if !sample_rates.contains(&range) {
+                    sample_rates.push(range);
+                }
+                if !channels.contains(&config.channels()) {
+                    channels.push(config.channels());
+                }
+            }
+        }
+        Err(err) => warn!(device = %name, error = %err, "failed to query input configs"),
+    }
+    sample_rates.sort_by_key(|range| (range.min, range.max));
+    channels.sort_unstable();
+
+    InputDeviceInfo {
+        is_default,
+        default_sample_rate: device
+            .default_input_config()
+            .ok()
+            .map(|config| config.sample_rate().0),
+        id,
+        name,
+        sample_formats,
+        sample_rates,
+        channels,
+    }
+}
@@ -0,0 +1,839 @@
+use std::f64::consts::PI;
+
+use crate::settings::ProcessingSettings;
+
+/// Kernel half-width in input samples when upsampling; 32 taps in total. When
+/// downsampling it grows with the ratio, so the transition band keeps the same
+/// width relative to the output rate instead of widening as the cutoff drops.
+const HALF_TAPS: usize = 16;
+/// Fractional positions tabulated per input sample; between two of them the
+/// kernel is interpolated linearly.
+const PHASES: usize = 256;
+/// Passband edge relative to the lower Nyquist frequency, leaving room for
+/// the transition band below it.
+const CUTOFF: f64 = 0.92;
+
+/// Speech has little energy below this; rumble, handling noise and DC do.
+const HIGH_PASS_HZ: f64 = 80.0;
+
+/// Noise suppression frame; about 21 ms at 24 kHz and 32 ms at 16 kHz. Frames
+/// overlap by half, which is also the added latency.
+const NS_FRAME: usize = 512;
+const NS_HOP: usize = NS_FRAME / 2;
+/// Attenuation limit, so that noise is reduced rather than gated to silence.
+const NS_GAIN_FLOOR: f32 = 0.1;
+/// How much more noise is subtracted than estimated. The estimate follows
+/// the dips of the noise rather than its mean, and residual noise is worse
+/// for transcription than slightly thinner speech.
+const NS_OVERSUBTRACTION: f32 = 4.0;
+/// Fastest the noise estimate may rise, so that speech is not learned as
+/// noise.
+const NS_NOISE_RISE_DB_PER_S: f32 = 2.0;
+/// Smoothing of the per-bin power and gains across frames; damps the
+/// "musical noise" typical of spectral subtraction.
+const NS_POWER_SMOOTHING: f32 = 0.6;
+/// Slower smoothing of the level the noise estimate follows.
+const NS_LEVEL_SMOOTHING: f32 = 0.9;
+const NS_GAIN_SMOOTHING: f32 = 0.5;
+
+/// Speech level the gain control aims for.
+const AGC_TARGET_DBFS: f32 = -20.0;
+const AGC_MAX_GAIN_DB: f32 = 24.0;
+const AGC_MIN_GAIN_DB: f32 = -6.0;
+/// Blocks quieter than this are pauses or noise and leave the gain alone.
+const AGC_GATE_DBFS: f32 = -50.0;
+const AGC_BLOCK_MS: u32 = 10;
+/// Gain reductions are fast so loud onsets do not clip; increases are slow
+/// so pauses between words are not pumped up.
+const AGC_ATTACK_S: f64 = 0.05;
+const AGC_RELEASE_S: f64 = 1.0;
+/// The speech level averages the power of blocks with signal over about this
+/// long, so that the quiet end of a syllable does not raise the gain for the
+/// next one.
+const AGC_LEVEL_S: f32 = 0.5;
+/// Above this the output is compressed smoothly instead of clipping.
+const LIMITER_KNEE: f32 = 0.9;
+
+/// Streaming band-limited resampler: a Blackman-windowed sinc low-pass whose
+/// cutoff sits below the lower of the two Nyquist frequencies, evaluated at
+/// arbitrary fractional positions from a polyphase table. Flat to 0.01 dB up
+/// to 75% of the lower Nyquist frequency and at least 70 dB down from 110% of
+/// it. Adds `half_taps` input samples of latency, which `flush` drains.
+pub struct Resampler {
+    /// Input samples advanced per output sample.
+    step: f64,
+    /// Position of the next output sample in `buffer`.
+    pos: f64,
+    buffer: Vec<f32>,
+    /// Kernel half-width in input samples.
+    half_taps: usize,
+    /// `PHASES + 1` rows of `2 * half_taps` coefficients.
+    table: Vec<f32>,
+    passthrough: bool,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let passthrough = input_rate == output_rate || input_rate == 0 || output_rate == 0;
+        let step = if passthrough {
+            1.0
+        } else {
+            f64::from(input_rate) / f64::from(output_rate)
+        };
+        // Cutoff in cycles per input sample times two, i.e. relative to the input Nyquist
+        let cutoff = CUTOFF * (1.0 / step).min(1.0);
+        let half_taps = (HALF_TAPS as f64 * step.max(1.0)).ceil() as usize;
+        Self {
+            step,
+            pos: (half_taps - 1) as f64,
+            // Leading silence centres the first output on the first input sample
+            buffer: vec![0.0; half_taps - 1],
+            half_taps,
+            table: if passthrough {
+                Vec::new()
+            } else {
+                build_table(cutoff, half_taps)
+            },
+            passthrough,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        if self.passthrough {
+            out.extend_from_slice(input);
+            return;
+        }
+        self.buffer.extend_from_slice(input);
+        let half = self.half_taps;
+        let taps = 2 * half;
+        out.reserve((input.len() as f64 / self.step) as usize + 1);
+        loop {
+            let index = self.pos.floor() as usize;
+            // Taps span index - half + 1 ..= index + half
+            if index + half >= self.buffer.len() {
+                break;
+            }
+            let phase = (self.pos - index as f64) * PHASES as f64;
+            let row = phase.floor() as usize;
+            let blend = (phase - row as f64) as f32;
+            let lower = &self.table[row * taps..(row + 1) * taps];
+            let upper = &self.table[(row + 1) * taps..(row + 2) * taps];
+            let window = &self.buffer[index + 1 - half..=index + half];
+            let mut acc = 0.0f32;
+            for ((sample, a), b) in window.iter().zip(lower).zip(upper) {
+                acc += sample * (a + (b - a) * blend);
+            }
+            out.push(acc);
+            self.pos += self.step;
+        }
+        // Keep only the samples the next output still needs
+        let first_needed = (self.pos.floor() as usize + 1).saturating_sub(half);
+        if first_needed > 0 {
+            let drained = first_needed.min(self.buffer.len());
+            self.buffer.drain(..drained);
+            self.pos -= drained as f64;
+        }
+    }
+
+    /// Pushes out the samples still held back by the filter latency.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        if !self.passthrough {
+            self.process(&vec![0.0; self.half_taps], out);
+        }
+    }
+}
+
+fn build_table(cutoff: f64, half_taps: usize) -> Vec<f32> {
+    let taps = 2 * half_taps;
+    let mut table = Vec::with_capacity((PHASES + 1) * taps);
+    for phase in 0..=PHASES {
+        let frac = phase as f64 / PHASES as f64;
+        let start = table.len();
+        for tap in 0..taps {
+            // Distance from the output position to input sample `tap`
+            let distance = frac + (half_taps as f64 - 1.0) - tap as f64;
+            table.push(kernel(distance, cutoff, half_taps as f64) as f32);
+        }
+        // Unity gain at DC for every phase
+        let sum: f32 = table[start..].iter().sum();
+        if sum.abs() > f32::EPSILON {
+            for coefficient in &mut table[start..] {
+                *coefficient /= sum;
+            }
+        }
+    }
+    table
+}
+
+fn kernel(distance: f64, cutoff: f64, half: f64) -> f64 {
+    if distance.abs() >= half {
+        return 0.0;
+    }
+    let x = cutoff * distance;
+    let sinc = if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    };
+    let ratio = distance / half;
+    let blackman = 0.42 + 0.5 * (PI * ratio).cos() + 0.08 * (2.0 * PI * ratio).cos();
+    cutoff * sinc * blackman
+}
+
+/// Optional clean-up between resampling and the backend: high-pass filter,
+/// noise suppression and automatic gain control, in that order. Works on the
+/// output rate, independent of the device.
+pub struct VoiceProcessor {
+    high_pass: Option<HighPass>,
+    noise: Option<NoiseSuppressor>,
+    gain: Option<AutoGain>,
+    scratch: Vec<f32>,
+    denoised: Vec<f32>,
+}
+
+impl VoiceProcessor {
+    pub fn new(sample_rate: u32, settings: &ProcessingSettings) -> Self {
+        Self {
+            high_pass: settings
+                .high_pass
+                .then(|| HighPass::new(sample_rate, HIGH_PASS_HZ)),
+            noise: settings
+                .noise_suppression
+                .then(|| NoiseSuppressor::new(sample_rate)),
+            gain: settings.auto_gain.then(|| AutoGain::new(sample_rate)),
+            scratch: Vec::new(),
+            denoised: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        self.scratch.clear();
+        self.scratch.extend_from_slice(input);
+        if let Some(high_pass) = &mut self.high_pass {
+            high_pass.process(&mut self.scratch);
+        }
+        if let Some(noise) = &mut self.noise {
+            self.denoised.clear();
+            noise.process(&self.scratch, &mut self.denoised);
+            std::mem::swap(&mut self.scratch, &mut self.denoised);
+        }
+        self.finish_into(out);
+    }
+
+    /// Pushes out the audio still held back by noise suppression.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        self.scratch.clear();
+        if let Some(noise) = &mut self.noise {
+            noise.flush(&mut self.scratch);
+        }
+        self.finish_into(out);
+    }
+
+    fn finish_into(&mut self, out: &mut Vec<f32>) {
+        if let Some(gain) = &mut self.gain {
+            gain.process(&mut self.scratch);
+        }
+        out.extend_from_slice(&self.scratch);
+    }
+}
+
+/// Second-order Butterworth high-pass (RBJ biquad, transposed direct form II).
+struct HighPass {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl HighPass {
+    fn new(sample_rate: u32, cutoff_hz: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / f64::from(sample_rate.max(1));
+        let cos = w0.cos();
+        let alpha = w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let x = f64::from(*sample);
+            let y = self.b0 * x + self.z1;
+            self.z1 = self.b1 * x - self.a1 * y + self.z2;
+            self.z2 = self.b2 * x - self.a2 * y;
+            *sample = y as f32;
+        }
+    }
+}
+
+/// Spectral noise suppression: a short-time Fourier transform with
+/// square-root Hann windows at 50% overlap, a per-bin noise estimate that
+/// follows the quietest recent level, and a Wiener-style gain per bin.
+/// Steady noise such as fans, air conditioning and hum is attenuated by
+/// up to 20 dB while speech passes.
+struct NoiseSuppressor {
+    window: Vec<f32>,
+    twiddles: Vec<(f32, f32)>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+    power: Vec<f32>,
+    level: Vec<f32>,
+    noise: Vec<f32>,
+    gains: Vec<f32>,
+    /// Per-frame factor by which the noise estimate may grow.
+    noise_rise: f32,
+    initialized: bool,
+    input: Vec<f32>,
+    overlap: Vec<f32>,
+    /// Leading output that only covers the initial zero padding.
+    skip: usize,
+    received: usize,
+    emitted: usize,
+}
+
+impl NoiseSuppressor {
+    fn new(sample_rate: u32) -> Self {
+        let bins = NS_FRAME / 2 + 1;
+        let frames_per_s = sample_rate.max(1) as f32 / NS_HOP as f32;
+        Self {
+            // Periodic square-root Hann: analysis times synthesis sums to one
+            window: (0..NS_FRAME)
+                .map(|n| (PI * n as f64 / NS_FRAME as f64).sin() as f32)
+                .collect(),
+            twiddles: (0..NS_FRAME / 2)
+                .map(|k| {
+                    let angle = -2.0 * PI * k as f64 / NS_FRAME as f64;
+                    (angle.cos() as f32, angle.sin() as f32)
+                })
+                .collect(),
+            re: vec![0.0; NS_FRAME],
+            im: vec![0.0; NS_FRAME],
+            power: vec![0.0; bins],
+            level: vec![0.0; bins],
+            noise: vec![0.0; bins],
+            gains: vec![1.0; bins],
+            noise_rise: 10f32.powf(NS_NOISE_RISE_DB_PER_S / 10.0 / frames_per_s),
+            initialized: false,
+            input: vec![0.0; NS_FRAME - NS_HOP],
+            overlap: vec![0.0; NS_FRAME],
+            skip: NS_FRAME - NS_HOP,
+            received: 0,
+            emitted: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        self.received += samples.len();
+        self.input.extend_from_slice(samples);
+        while self.input.len() >= NS_FRAME {
+            self.process_frame();
+            let mut start = 0;
+            if self.skip > 0 {
+                start = self.skip.min(NS_HOP);
+                self.skip -= start;
+            }
+            out.extend_from_slice(&self.overlap[start..NS_HOP]);
+            self.emitted += NS_HOP - start;
+            self.overlap.copy_within(NS_HOP.., 0);
+            self.overlap[NS_FRAME - NS_HOP..].fill(0.0);
+            self.input.drain(..NS_HOP);
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<f32>) {
+        let pending = self.received - self.emitted;
+        let mut tail = Vec::with_capacity(pending + NS_FRAME);
+        let received = self.received;
+        self.process(&[0.0; NS_FRAME], &mut tail);
+        self.received = received;
+        out.extend_from_slice(&tail[..pending.min(tail.len())]);
+        self.emitted = self.received;
+    }
+
+    fn process_frame(&mut self) {
+        for (n, (re, im)) in self.re.iter_mut().zip(&mut self.im).enumerate() {
+            *re = self.input[n] * self.window[n];
+            *im = 0.0;
+        }
+        fft(&mut self.re, &mut self.im, &self.twiddles, false);
+
+        let bins = self.power.len();
+        for k in 0..bins {
+            let power = self.re[k] * self.re[k] + self.im[k] * self.im[k];
+            if !self.initialized {
+                self.power[k] = power;
+                self.level[k] = power;
+                self.noise[k] = power;
+            }
+            self.power[k] = NS_POWER_SMOOTHING * self.power[k] + (1.0 - NS_POWER_SMOOTHING) * power;
+            self.level[k] = NS_LEVEL_SMOOTHING * self.level[k] + (1.0 - NS_LEVEL_SMOOTHING) * power;
+            // Follow quieter levels at once, louder ones only slowly
+            self.noise[k] = self.level[k].min(self.noise[k] * self.noise_rise);
+            let ratio = self.noise[k] / self.power[k].max(f32::MIN_POSITIVE);
+            let gain = (1.0 - NS_OVERSUBTRACTION * ratio).max(0.0).sqrt();
+            self.gains[k] = (NS_GAIN_SMOOTHING * self.gains[k] + (1.0 - NS_GAIN_SMOOTHING) * gain)
+                .max(NS_GAIN_FLOOR);
+        }
+        self.initialized = true;
+        for k in 0..bins {
+            let gain = self.gains[k];
+            self.re[k] *= gain;
+            self.im[k] *= gain;
+            // Mirror onto the negative frequencies to keep the signal real
+            if k > 0 && k < NS_FRAME - k {
+                self.re[NS_FRAME - k] *= gain;
+                self.im[NS_FRAME - k] *= gain;
+            }
+        }
+
+        fft(&mut self.re, &mut self.im, &self.twiddles, true);
+        let scale = 1.0 / NS_FRAME as f32;
+        for n in 0..NS_FRAME {
+            self.overlap[n] += self.re[n] * scale * self.window[n];
+        }
+    }
+}
+
+/// In-place iterative radix-2 FFT. `inverse` computes the unscaled inverse.
+fn fft(re: &mut [f32], im: &mut [f32], twiddles: &[(f32, f32)], inverse: bool) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let stride = n / len;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = twiddles[k * stride];
+                let wi = if inverse { -wi } else { wi };
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * wr - im[b] * wi;
+                let ti = re[b] * wi + im[b] * wr;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Automatic gain control: measures the level of each short block, tracks the
+/// speech level from blocks that contain signal, moves the gain towards the
+/// target for that level, and applies it sample by sample with a soft limiter
+/// in front of full scale.
+struct AutoGain {
+    block_len: usize,
+    block_fill: usize,
+    block_energy: f64,
+    speech_power: Option<f64>,
+    /// Weight of each new block in `speech_power`.
+    level_weight: f64,
+    /// Linear gain currently applied and the one it is moving towards.
+    gain: f32,
+    target: f32,
+    attack: f32,
+    release: f32,
+}
+
+impl AutoGain {
+    fn new(sample_rate: u32) -> Self {
+        let rate = f64::from(sample_rate.max(1));
+        let coefficient = |seconds: f64| (1.0 - (-1.0 / (seconds * rate)).exp()) as f32;
+        Self {
+            block_len: ((sample_rate * AGC_BLOCK_MS / 1000) as usize).max(1),
+            block_fill: 0,
+            block_energy: 0.0,
+            speech_power: None,
+            level_weight: f64::from(AGC_BLOCK_MS as f32 / 1000.0 / AGC_LEVEL_S).min(1.0),
+            gain: 1.0,
+            target: 1.0,
+            attack: coefficient(AGC_ATTACK_S),
+            release: coefficient(AGC_RELEASE_S),
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.block_energy += f64::from(*sample) * f64::from(*sample);
+            self.block_fill += 1;
+            if self.block_fill == self.block_len {
+                let rms = (self.block_energy / self.block_len as f64).sqrt() as f32;
+                let level_db = 20.0 * rms.max(1e-9).log10();
+                if level_db > AGC_GATE_DBFS {
+                    let power = f64::from(rms * rms);
+                    let speech_power = self.speech_power.map_or(power, |speech| {
+                        speech + (power - speech) * self.level_weight
+                    });
+                    self.speech_power = Some(speech_power);
+                    let speech_db = 10.0 * speech_power.log10() as f32;
+                    let gain_db =
+                        (AGC_TARGET_DBFS - speech_db).clamp(AGC_MIN_GAIN_DB, AGC_MAX_GAIN_DB);
+                    self.target = 10f32.powf(gain_db / 20.0);
+                }
+                self.block_fill = 0;
+                self.block_energy = 0.0;
+            }
+            let rate = if self.target < self.gain {
+                self.attack
+            } else {
+                self.release
+            };
+            self.gain += (self.target - self.gain) * rate;
+            *sample = soft_limit(*sample * self.gain);
+        }
+    }
+}
+
+/// Passes values below `LIMITER_KNEE` unchanged and bends larger ones
+/// smoothly towards full scale.
+fn soft_limit(value: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= LIMITER_KNEE {
+        return value;
+    }
+    let headroom = 1.0 - LIMITER_KNEE;
+    let limited = LIMITER_KNEE + headroom * ((magnitude - LIMITER_KNEE) / headroom).tanh();
+    limited.copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The filter's design targets: flat up to this share of the lower Nyquist
+    /// frequency, and at least `STOPBAND_DB` down from `STOPBAND_EDGE` on.
+    const PASSBAND_EDGE: f64 = 0.75;
+    const PASSBAND_TOLERANCE_DB: f64 = 0.01;
+    const STOPBAND_EDGE: f64 = 1.1;
+    const STOPBAND_DB: f64 = 70.0;
+    const RATES: [(u32, u32); 3] = [(44_100, 24_000), (48_000, 24_000), (16_000, 24_000)];
+    const AMPLITUDE: f64 = 0.5;
+
+    fn sine(rate: u32, hz: f64, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| (AMPLITUDE * (2.0 * PI * hz * n as f64 / f64::from(rate)).sin()) as f32)
+            .collect()
+    }
+
+    /// Runs `input` through a resampler in callback-sized chunks.
+    fn resample(input_rate: u32, output_rate: u32, input: &[f32]) -> Vec<f32> {
+        let mut resampler = Resampler::new(input_rate, output_rate);
+        let mut out = Vec::new();
+        for chunk in input.chunks(480) {
+            resampler.process(chunk, &mut out);
+        }
+        resampler.flush(&mut out);
+        out
+    }
+
+    /// One second of `hz` resampled, without the edges where the filter
+    /// sees the start and end of the tone.
+    fn steady_tone(input_rate: u32, output_rate: u32, hz: f64) -> Vec<f32> {
+        let input = sine(input_rate, hz, input_rate as usize);
+        let out = resample(input_rate, output_rate, &input);
+        let edge = output_rate as usize / 10;
+        out[edge..out.len() - edge].to_vec()
+    }
+
+    /// Sine and cosine amplitudes of `hz` in `samples`, by least squares.
+    fn fit(samples: &[f32], rate: u32, hz: f64) -> (f64, f64) {
+        let (mut sin, mut cos) = (0.0, 0.0);
+        for (n, sample) in samples.iter().enumerate() {
+            let phase = 2.0 * PI * hz * n as f64 / f64::from(rate);
+            sin += f64::from(*sample) * phase.sin();
+            cos += f64::from(*sample) * phase.cos();
+        }
+        let scale = 2.0 / samples.len() as f64;
+        (sin * scale, cos * scale)
+    }
+
+    /// Level of `samples` relative to the RMS of the input sine.
+    fn level_db(samples: impl Iterator<Item = f64> + Clone) -> f64 {
+        let count = samples.clone().count() as f64;
+        let rms = (samples.map(|x| x * x).sum::<f64>() / count).sqrt();
+        20.0 * (rms / (AMPLITUDE / 2f64.sqrt())).log10()
+    }
+
+    #[test]
+    fn resampler_passband_has_unity_gain() {
+        for (input_rate, output_rate) in RATES {
+            let nyquist = f64::from(input_rate.min(output_rate)) / 2.0;
+            for share in [0.01, 0.1, 0.3, 0.5, PASSBAND_EDGE] {
+                let hz = nyquist * share;
+                let out = steady_tone(input_rate, output_rate, hz);
+                let (sin, cos) = fit(&out, output_rate, hz);
+                let gain_db = 20.0 * ((sin * sin + cos * cos).sqrt() / AMPLITUDE).log10();
+                assert!(
+                    gain_db.abs() < PASSBAND_TOLERANCE_DB,
+                    "{input_rate} -> {output_rate} Hz at {hz} Hz: {gain_db:.4} dB"
+                );
+                // Whatever is not the tone itself is aliasing or imaging
+                let spurious = out.iter().enumerate().map(|(n, x)| {
+                    let phase = 2.0 * PI * hz * n as f64 / f64::from(output_rate);
+                    f64::from(*x) - sin * phase.sin() - cos * phase.cos()
+                });
+                let spurious_db = level_db(spurious);
+                assert!(
+                    spurious_db < -STOPBAND_DB,
+                    "{input_rate} -> {output_rate} Hz at {hz} Hz: images at {spurious_db:.1} dB"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn resampler_rejects_tones_above_output_nyquist() {
+        for (input_rate, output_rate) in RATES.into_iter().filter(|(i, o)| i > o) {
+            let nyquist = f64::from(output_rate) / 2.0;
+            for share in [STOPBAND_EDGE, 1.3, 1.6, 1.8] {
+                let hz = nyquist * share;
+                let out = steady_tone(input_rate, output_rate, hz);
+                let level = level_db(out.iter().map(|x| f64::from(*x)));
+                assert!(
+                    level < -STOPBAND_DB,
+                    "{input_rate} -> {output_rate} Hz at {hz} Hz: aliased at {level:.1} dB"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn resampler_flush_yields_full_length_without_delay() {
+        for (input_rate, output_rate) in RATES {
+            let len = 10_000;
+            let at = 3_000;
+            let mut impulse = vec![0.0; len];
+            impulse[at] = 1.0;
+            let step = f64::from(input_rate) / f64::from(output_rate);
+
+            let mut resampler = Resampler::new(input_rate, output_rate);
+            let mut out = Vec::new();
+            resampler.process(&impulse, &mut out);
+            // The filter latency is held back until the flush
+            let held_back = len as f64 / step - out.len() as f64;
+            assert!(
+                held_back > 0.0 && held_back <= resampler.half_taps as f64 / step + 1.0,
+                "{input_rate} -> {output_rate} Hz: {held_back} samples held back"
+            );
+            resampler.flush(&mut out);
+            // Exact up to the rounding of the accumulated position
+            let expected = len as f64 / step;
+            assert!(
+                (out.len() as f64 - expected).abs() <= 1.0,
+                "{input_rate} -> {output_rate} Hz: {} samples, expected {expected}",
+                out.len()
+            );
+
+            let peak = out
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(index, _)| index);
+            assert_eq!(peak, Some((at as f64 / step).round() as usize));
+        }
+    }
+
+    #[test]
+    fn resampler_passes_equal_rates_through() {
+        let input = sine(24_000, 440.0, 1_000);
+        assert_eq!(resample(24_000, 24_000, &input), input);
+    }
+
+    /// 20 ms at the fixtures' 16 kHz.
+    const BLOCK: usize = 320;
+
+    /// Reads `tests/fixtures/{name}.wav`, 16-bit mono.
+    fn fixture(name: &str) -> (u32, Vec<f32>) {
+        let path = format!("{}/tests/fixtures/{name}.wav", env!("CARGO_MANIFEST_DIR"));
+        let mut reader =
+            hound::WavReader::open(&path).unwrap_or_else(|err| panic!("{path}: {err}"));
+        let rate = reader.spec().sample_rate;
+        let samples = reader
+            .samples::<i16>()
+            .map(|sample| f32::from(sample.expect("valid sample")) / 32_768.0)
+            .collect();
+        (rate, samples)
+    }
+
+    fn processing(high_pass: bool, noise_suppression: bool, auto_gain: bool) -> ProcessingSettings {
+        ProcessingSettings {
+            high_pass,
+            noise_suppression,
+            auto_gain,
+        }
+    }
+
+    /// Runs `input` through a processor in frame-sized chunks, then flushes.
+    fn process(rate: u32, settings: &ProcessingSettings, input: &[f32]) -> Vec<f32> {
+        let mut processor = VoiceProcessor::new(rate, settings);
+        let mut out = Vec::new();
+        for chunk in input.chunks(BLOCK) {
+            processor.process(chunk, &mut out);
+        }
+        processor.flush(&mut out);
+        out
+    }
+
+    fn power(samples: &[f32]) -> f64 {
+        samples.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>() / samples.len() as f64
+    }
+
+    fn mix(speech: &[f32], noise: &[f32], snr_db: f64) -> Vec<f32> {
+        let gain = (power(speech) / power(noise) / 10f64.powf(snr_db / 10.0)).sqrt() as f32;
+        speech
+            .iter()
+            .zip(noise)
+            .map(|(s, n)| s + n * gain)
+            .collect()
+    }
+
+    /// Ratio of the power in blocks where the clean speech is active to the
+    /// power in its pauses, skipping the first second while the noise
+    /// estimate settles.
+    fn snr_db(clean: &[f32], signal: &[f32], rate: u32) -> f64 {
+        let skip = rate as usize / BLOCK;
+        let peak = clean.chunks(BLOCK).map(power).fold(0.0, f64::max);
+        let (mut speech, mut pauses) = (Vec::new(), Vec::new());
+        for (clean, block) in clean.chunks(BLOCK).zip(signal.chunks(BLOCK)).skip(skip) {
+            let level = power(clean);
+            if level > peak / 100.0 {
+                speech.push(power(block));
+            } else if level < peak / 1e6 {
+                pauses.push(power(block));
+            }
+        }
+        assert!(!speech.is_empty() && !pauses.is_empty());
+        let mean = |powers: &[f64]| powers.iter().sum::<f64>() / powers.len() as f64;
+        10.0 * (mean(&speech) / mean(&pauses)).log10()
+    }
+
+    #[test]
+    fn processing_passes_audio_through_when_disabled() {
+        let (rate, speech) = fixture("speech");
+        let (_, noise) = fixture("white_noise");
+        let input = mix(&speech, &noise, 10.0);
+        assert_eq!(process(rate, &ProcessingSettings::default(), &input), input);
+    }
+
+    #[test]
+    fn high_pass_removes_dc_and_mains_hum() {
+        let (rate, speech) = fixture("speech");
+        let (_, hum) = fixture("hum");
+        let input: Vec<f32> = mix(&speech, &hum, 0.0)
+            .iter()
+            .map(|sample| sample + 0.1)
+            .collect();
+        let out = process(rate, &processing(true, false, false), &input);
+        assert_eq!(out.len(), input.len());
+
+        let settled = rate as usize;
+        let amplitude = |samples: &[f32], hz: f64| {
+            let (sin, cos) = fit(samples, rate, hz);
+            (sin * sin + cos * cos).sqrt()
+        };
+        let mean = out[settled..].iter().map(|x| f64::from(*x)).sum::<f64>()
+            / (out.len() - settled) as f64;
+        assert!(mean.abs() < 1e-3, "DC left: {mean}");
+        let before = amplitude(&input[settled..], 50.0);
+        let after = amplitude(&out[settled..], 50.0);
+        let attenuation_db = 20.0 * (before / after).log10();
+        // A second-order 80 Hz high-pass is 8.8 dB down at 50 Hz
+        assert!(attenuation_db > 8.5, "50 Hz down by {attenuation_db:.1} dB");
+    }
+
+    #[test]
+    fn noise_suppression_improves_snr_for_each_noise() {
+        let (rate, speech) = fixture("speech");
+        // Steady hum is easiest; pink noise shares the low band with voices
+        for (name, min_gain_db) in [("white_noise", 4.0), ("pink_noise", 2.0), ("hum", 15.0)] {
+            let (_, noise) = fixture(name);
+            let input = mix(&speech, &noise, 10.0);
+            let out = process(rate, &processing(false, true, false), &input);
+            assert_eq!(out.len(), input.len());
+            let before = snr_db(&speech, &input, rate);
+            let after = snr_db(&speech, &out, rate);
+            assert!(
+                after - before > min_gain_db,
+                "{name}: SNR {before:.1} dB -> {after:.1} dB"
+            );
+        }
+    }
+
+    #[test]
+    fn auto_gain_converges_without_clipping() {
+        // Share of samples at full scale that the input meter warns about
+        const CLIP_LEVEL: f32 = 0.999;
+        const CLIP_RATIO: f64 = 0.001;
+        let (rate, speech) = fixture("speech");
+        // Quiet, as recorded, and peaking at full scale
+        for scale in [0.25, 1.0, 2.0] {
+            let input: Vec<f32> = speech.iter().map(|sample| sample * scale).collect();
+            let out = process(rate, &processing(false, false, true), &input);
+            let clipped = out.iter().filter(|x| x.abs() >= CLIP_LEVEL).count();
+            assert!(
+                (clipped as f64) < out.len() as f64 * CLIP_RATIO,
+                "scale {scale}: {clipped} samples clipped"
+            );
+
+            // Speech in the second half sits at the target
+            let half = out.len() / 2;
+            let speech_blocks: Vec<f64> = speech[half..]
+                .chunks(BLOCK)
+                .zip(out[half..].chunks(BLOCK))
+                .filter(|(clean, _)| 10.0 * power(clean).log10() > -40.0)
+                .map(|(_, block)| power(block))
+                .collect();
+            let level_db =
+                10.0 * (speech_blocks.iter().sum::<f64>() / speech_blocks.len() as f64).log10();
+            assert!(
+                (level_db - f64::from(AGC_TARGET_DBFS)).abs() < 2.0,
+                "scale {scale}: speech at {level_db:.1} dBFS"
+            );
+        }
+    }
+
+    #[test]
+    fn flush_emits_exactly_the_held_back_tail() {
+        let (rate, speech) = fixture("speech");
+        for len in [speech.len(), speech.len() - 77, 100] {
+            let input = &speech[..len];
+            let mut processor = VoiceProcessor::new(rate, &processing(true, true, true));
+            let mut out = Vec::new();
+            for chunk in input.chunks(BLOCK) {
+                processor.process(chunk, &mut out);
+            }
+            let held_back = len - out.len();
+            assert!(held_back <= NS_FRAME, "{len}: {held_back} held back");
+            let mut tail = Vec::new();
+            processor.flush(&mut tail);
+            assert_eq!(tail.len(), held_back, "{len}");
+            // Nothing more once flushed
+            processor.flush(&mut tail);
+            assert_eq!(tail.len(), held_back, "{len}");
+        }
+    }
+}
@@ -14,12 +14,18 @@ pub enum AppError {
     AudioStreamUnavailable,
     #[error("Audio input device error: {0}")]
     AudioDevice(String),
+    #[error("Audio input device lost: {0}")]
+    AudioDeviceLost(String),
+    #[error("Transcription could not keep up with the audio: {0}")]
+    AudioOverrun(String),
     #[error("Failed to initialize audio capture: {0}")]
     AudioInit(String),
     #[error("No audio samples captured")]
     AudioEmpty,
     #[error("Realtime service error: {0}")]
     Realtime(String),
+    #[error("Local transcription error: {0}")]
+    LocalEngine(String),
     #[error("Missing OpenAI API key")]
     MissingApiKey,
     #[error("{0}")]
@@ -30,6 +36,8 @@ pub enum AppError {
     History(String),
     #[error("Settings error: {0}")]
     Settings(String),
+    #[error("Secret storage error: {0}")]
+    Secrets(String),
     #[error("Hotkey error: {0}")]
     Hotkey(String),
     #[error("{0}")]
@@ -43,21 +51,25 @@ impl AppError {
             AppError::RecorderNotRunning => "RECORDER_NOT_RUNNING",
             AppError::AudioStreamUnavailable => "AUDIO_STREAM_UNAVAILABLE",
             AppError::AudioDevice(_) => "AUDIO_DEVICE",
+            AppError::AudioDeviceLost(_) => "AUDIO_DEVICE_LOST",
+            AppError::AudioOverrun(_) => "AUDIO_OVERRUN",
             AppError::AudioInit(_) => "AUDIO_INIT",
             AppError::AudioEmpty => "AUDIO_EMPTY",
             AppError::Realtime(_) => "REALTIME",
+            AppError::LocalEngine(_) => "LOCAL_ENGINE",
             AppError::MissingApiKey => "MISSING_API_KEY",
             AppError::Validation(_) => "VALIDATION",
             AppError::Paste(_) => "PASTE",
             AppError::History(_) => "HISTORY",
             AppError::Settings(_) => "SETTINGS",
+            AppError::Secrets(_) => "SECRETS",
             AppError::Hotkey(_) => "HOTKEY",
             AppError::Internal(_) => "INTERNAL",
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommandError {
     pub code: &'static str,
     pub message: String,
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tracing::info;
+
+use crate::errors::{AppError, AppResult};
+use crate::fsutil::{persist, temp_path};
+use crate::history::{HistoryEntry, HistoryQuery, HistoryStore, NewHistoryEntry, SortOrder};
+
+const EXPORT_PAGE_SIZE: u32 = 500;
+/// File extensions `import_history` understands.
+pub const IMPORT_EXTENSIONS: &[&str] = &["jsonl", "ndjson", "csv"];
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// One JSON object per line with every history column.
+    JsonLines,
+    /// RFC 4180: header row, CRLF line endings, quoted where needed.
+    Csv,
+    /// Human-readable journal with one heading per day. Not importable.
+    Markdown,
+}
+
+impl ExportFormat {
+    /// Dialog filter name and file extension.
+    pub fn file_type(self) -> (&'static str, &'static str) {
+        match self {
+            ExportFormat::JsonLines => ("JSON Lines", "jsonl"),
+            ExportFormat::Csv => ("CSV", "csv"),
+            ExportFormat::Markdown => ("Markdown", "md"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Entries already present with the same text and timestamp, or with the
+    /// same text when the record has no timestamp.
+    pub skipped: usize,
+}
+
+/// One record of a JSON Lines export. Only `text` is required; `id` and
+/// `status` are ignored since they are derived on insert.
+#[derive(Debug, Deserialize)]
+struct ImportRecord {
+    text: String,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    duration_ms: Option<i64>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    sample_rate: Option<u32>,
+    #[serde(default)]
+    input_device: Option<String>,
+    #[serde(default)]
+    paste_outcome: Option<String>,
+    #[serde(default)]
+    source_app: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    original_text: Option<String>,
+    #[serde(default)]
+    edited_at: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// A CSV row. Tags are joined with `", "` since CSV has no nested values;
+/// tags never contain commas.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRow {
+    #[serde(default)]
+    id: Option<i64>,
+    text: String,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    duration_ms: Option<i64>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    sample_rate: Option<u32>,
+    #[serde(default)]
+    input_device: Option<String>,
+    #[serde(default)]
+    paste_outcome: Option<String>,
+    #[serde(default)]
+    source_app: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    original_text: Option<String>,
+    #[serde(default)]
+    edited_at: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    tags: String,
+}
+
+impl From<&HistoryEntry> for CsvRow {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            id: Some(entry.id),
+            text: entry.text.clone(),
+            created_at: Some(entry.created_at.clone()),
+            duration_ms: entry.duration_ms,
+            model: entry.model.clone(),
+            language: entry.language.clone(),
+            sample_rate: entry.sample_rate.and_then(|rate| u32::try_from(rate).ok()),
+            input_device: entry.input_device.clone(),
+            paste_outcome: entry.paste_outcome.clone(),
+            source_app: entry.source_app.clone(),
+            status: Some(entry.status.clone()),
+            error: entry.error.clone(),
+            original_text: entry.original_text.clone(),
+            edited_at: entry.edited_at.clone(),
+            pinned: entry.pinned,
+            tags: entry.tags.0.join(", "),
+        }
+    }
+}
+
+impl From<CsvRow> for ImportRecord {
+    fn from(row: CsvRow) -> Self {
+        Self {
+            text: row.text,
+            created_at: row.created_at,
+            duration_ms: row.duration_ms,
+            model: row.model,
+            language: row.language,
+            sample_rate: row.sample_rate,
+            input_device: row.input_device,
+            paste_outcome: row.paste_outcome,
+            source_app: row.source_app,
+            error: row.error,
+            original_text: row.original_text,
+            edited_at: row.edited_at,
+            pinned: row.pinned,
+            tags: row.tags.split(',').map(str::to_string).collect(),
+        }
+    }
+}
+
+/// Streams the entries matching `query` to `path`, oldest first, one page at a
+/// time. `query.cursor`, `limit` and `order` are overridden. The export is
+/// written to a temp file and renamed into place, so a failed export leaves
+/// an existing file at `path` untouched. Returns the number of entries written.
+pub async fn export_history(
+    store: &HistoryStore,
+    path: &Path,
+    format: ExportFormat,
+    query: HistoryQuery,
+) -> AppResult<usize> {
+    let tmp = temp_path(path);
+    let result = match write_export(store, &tmp, format, query).await {
+        Ok(written) => {
+            let (from, to) = (tmp.clone(), path.to_path_buf());
+            tokio::task::spawn_blocking(move || persist(&from, &to))
+                .await
+                .map_err(|err| AppError::History(err.to_string()))
+                .and_then(|persisted| persisted.map_err(io_error))
+                .map(|()| written)
+        }
+        Err(err) => Err(err),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    let written = result?;
+    info!(path = %path.display(), ?format, entries = written, "exported history");
+    Ok(written)
+}
+
+async fn write_export(
+    store: &HistoryStore,
+    tmp: &Path,
+    format: ExportFormat,
+    mut query: HistoryQuery,
+) -> AppResult<usize> {
+    query.cursor = None;
+    query.limit = Some(EXPORT_PAGE_SIZE);
+    query.order = SortOrder::OldestFirst;
+
+    let file = File::create(tmp).await.map_err(io_error)?;
+    let mut out = BufWriter::new(file);
+    let mut written = 0;
+    let mut current_day: Option<String> = None;
+
+    loop {
+        let page = store.search(&query).await?;
+        let entries: Vec<HistoryEntry> = page.entries.into_iter().map(|hit| hit.entry).collect();
+        let chunk = match format {
+            ExportFormat::JsonLines => json_lines(&entries)?,
+            ExportFormat::Csv => csv_rows(&entries, written == 0)?,
+            ExportFormat::Markdown => markdown(&entries, &mut current_day),
+        };
+        out.write_all(&chunk).await.map_err(io_error)?;
+        written += entries.len();
+
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+
+    if written == 0 && matches!(format, ExportFormat::Csv) {
+        out.write_all(&csv_rows(&[], true)?)
+            .await
+            .map_err(io_error)?;
+    }
+    out.flush().await.map_err(io_error)?;
+    out.get_ref().sync_all().await.map_err(io_error)?;
+    Ok(written)
+}
+
+/// Imports a JSON Lines (`.jsonl`, `.ndjson`) or CSV export. Entries whose text
+/// hash and timestamp match an existing entry, or an earlier one in the same
+/// file, are skipped. Entries without a timestamp are stored as of now, so they
+/// are skipped when their text matches any entry at all.
+pub async fn import_history(store: &HistoryStore, path: &Path) -> AppResult<ImportReport> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let raw = tokio::fs::read(path).await.map_err(io_error)?;
+    let records = match extension.as_str() {
+        "jsonl" | "ndjson" => parse_json_lines(&raw)?,
+        "csv" => parse_csv(&raw)?,
+        other => {
+            return Err(AppError::Validation(format!(
+                "cannot import .{other} files; use a JSON Lines or CSV export"
+            )))
+        }
+    };
+
+    let mut seen: HashSet<(String, String)> = store
+        .fingerprints()
+        .await?
+        .into_iter()
+        .map(|(text, created_at)| fingerprint(&text, &created_at))
+        .collect();
+    let mut seen_texts: HashSet<String> = seen.iter().map(|(hash, _)| hash.clone()).collect();
+
+    let mut fresh = Vec::new();
+    let mut skipped = 0;
+    for record in records {
+        let created_at = record.created_at.as_deref().map(normalize_timestamp);
+        let (hash, timestamp) =
+            fingerprint(&record.text, created_at.as_deref().unwrap_or_default());
+        let duplicate = match created_at {
+            Some(_) => seen.contains(&(hash.clone(), timestamp.clone())),
+            None => seen_texts.contains(&hash),
+        };
+        if record.text.trim().is_empty() || duplicate {
+            skipped += 1;
+            continue;
+        }
+        seen_texts.insert(hash.clone());
+        seen.insert((hash, timestamp));
+        fresh.push(NewHistoryEntry {
+            text: record.text,
+            created_at,
+            duration_ms: record.duration_ms,
+            model: record.model,
+            language: record.language,
+            sample_rate: record.sample_rate,
+            input_device: record.input_device,
+            paste_outcome: record.paste_outcome,
+            source_app: record.source_app,
+            error: record.error,
+            original_text: record.original_text,
+            edited_at: record.edited_at,
+            pinned: record.pinned,
+            tags: record.tags,
+        });
+    }
+
+    store.add_many(&fresh).await?;
+    info!(path = %path.display(), imported = fresh.len(), skipped, "imported history");
+    Ok(ImportReport {
+        imported: fresh.len(),
+        skipped,
+    })
+}
+
+fn json_lines(entries: &[HistoryEntry]) -> AppResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut buf, entry).map_err(|err| AppError::History(err.to_string()))?;
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+fn csv_rows(entries: &[HistoryEntry], with_header: bool) -> AppResult<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(Vec::new());
+    if with_header {
+        writer
+            .write_record(CSV_HEADER)
+            .map_err(|err| AppError::History(err.to_string()))?;
+    }
+    for entry in entries {
+        writer
+            .serialize(CsvRow::from(entry))
+            .map_err(|err| AppError::History(err.to_string()))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| AppError::History(err.to_string()))
+}
+
+/// Column order of `CsvRow`, which `csv` serializes positionally.
+const CSV_HEADER: &[&str] = &[
+    "id",
+    "text",
+    "created_at",
+    "duration_ms",
+    "model",
+    "language",
+    "sample_rate",
+    "input_device",
+    "paste_outcome",
+    "source_app",
+    "status",
+    "error",
+    "original_text",
+    "edited_at",
+    "pinned",
+    "tags",
+];
+
+fn markdown(entries: &[HistoryEntry], current_day: &mut Option<String>) -> Vec<u8> {
+    let mut out = String::new();
+    for entry in entries {
+        let (day, time) = split_timestamp(&entry.created_at);
+        if current_day.as_deref() != Some(day) {
+            if current_day.is_some() {
+                out.push('\n');
+            }
+            out.push_str(&format!("## {day}\n\n"));
+            *current_day = Some(day.to_string());
+        }
+        // Indent continuation lines so multi-line transcripts stay in one item
+        let text = entry.text.trim().replace('\n', "\n  ");
+        out.push_str(&format!("- **{time}** {text}\n"));
+    }
+    out.into_bytes()
+}
+
+/// Splits `YYYY-MM-DD HH:MM:SS` into the date and `HH:MM`.
+fn split_timestamp(created_at: &str) -> (&str, &str) {
+    let day = created_at.get(..10).unwrap_or(created_at);
+    let time = created_at.get(11..16).unwrap_or("");
+    (day, time)
+}
+
+fn parse_json_lines(raw: &[u8]) -> AppResult<Vec<ImportRecord>> {
+    let text = std::str::from_utf8(raw).map_err(|err| AppError::Validation(err.to_string()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| AppError::Validation(format!("line {}: {err}", index + 1)))
+        })
+        .collect()
+}
+
+fn parse_csv(raw: &[u8]) -> AppResult<Vec<ImportRecord>> {
+    csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(raw)
+        .deserialize::<CsvRow>()
+        .map(|row| {
+            row.map(ImportRecord::from)
+                .map_err(|err| AppError::Validation(err.to_string()))
+        })
+        .collect()
+}
+
+fn fingerprint(text: &str, created_at: &str) -> (String, String) {
+    let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+    (hash, normalize_timestamp(created_at))
+}
+
+/// Brings ISO 8601 variants (`T` separator, `Z` or `±hh:mm` offset,
+/// fractional seconds) to the UTC `YYYY-MM-DD HH:MM:SS` form SQLite stores.
+/// Timestamps without an offset are taken to be UTC already.
+fn normalize_timestamp(raw: &str) -> String {
+    const STORED: &str = "%Y-%m-%d %H:%M:%S";
+    let trimmed = raw.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(trimmed) {
+        return parsed.with_timezone(&Utc).format(STORED).to_string();
+    }
+    let naive = trimmed.trim_end_matches('Z').replacen('T', " ", 1);
+    if let Ok(parsed) = NaiveDateTime::parse_from_str(&naive, "%Y-%m-%d %H:%M:%S%.f") {
+        return parsed.format(STORED).to_string();
+    }
+    match naive.get(..19) {
+        Some(prefix) => prefix.to_string(),
+        None => naive,
+    }
+}
+
+fn io_error(err: std::io::Error) -> AppError {
+    AppError::History(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_normalized_to_stored_form() {
+        for raw in [
+            "2024-05-01 10:00:00",
+            "2024-05-01T10:00:00",
+            "2024-05-01T10:00:00Z",
+            "2024-05-01T10:00:00.250Z",
+            " 2024-05-01 10:00:00.5 ",
+        ] {
+            assert_eq!(normalize_timestamp(raw), "2024-05-01 10:00:00", "{raw}");
+        }
+    }
+
+    #[test]
+    fn offsets_are_converted_to_utc() {
+        assert_eq!(
+            normalize_timestamp("2024-05-01T10:00:00+02:00"),
+            "2024-05-01 08:00:00"
+        );
+        assert_eq!(
+            normalize_timestamp("2024-12-31T22:30:00.125-05:30"),
+            "2025-01-01 04:00:00"
+        );
+    }
+
+    #[test]
+    fn same_instant_has_one_fingerprint() {
+        assert_eq!(
+            fingerprint("hello", "2024-05-01T12:00:00+02:00"),
+            fingerprint("hello", "2024-05-01 10:00:00")
+        );
+    }
+}
@@ -1,28 +1,243 @@
 use std::str::FromStr;
 
 use directories::ProjectDirs;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
-    FromRow, Pool, Sqlite,
+    sqlite::{SqliteArguments, SqliteConnectOptions, SqlitePoolOptions},
+    FromRow, Pool, QueryBuilder, Sqlite, SqliteConnection,
 };
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::cipher::FieldCipher;
 use crate::errors::{AppError, AppResult};
+use crate::secrets::{SecretStore, HISTORY_KEY_NEXT_SECRET, HISTORY_KEY_SECRET};
+use crate::settings::RetentionPolicy;
+
+/// Schema migrations for history.db. `MIGRATIONS[n]` upgrades the database
+/// from `PRAGMA user_version = n` to `n + 1`; never edit an entry once shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: the original table; existing databases already have it.
+    r#"
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        text TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    "#,
+    // 2: per-session metadata.
+    r#"
+    ALTER TABLE history ADD COLUMN duration_ms INTEGER;
+    ALTER TABLE history ADD COLUMN model TEXT;
+    ALTER TABLE history ADD COLUMN language TEXT;
+    ALTER TABLE history ADD COLUMN sample_rate INTEGER;
+    ALTER TABLE history ADD COLUMN input_device TEXT;
+    ALTER TABLE history ADD COLUMN paste_outcome TEXT;
+    ALTER TABLE history ADD COLUMN source_app TEXT;
+    ALTER TABLE history ADD COLUMN status TEXT NOT NULL DEFAULT 'ok';
+    ALTER TABLE history ADD COLUMN error TEXT;
+    CREATE INDEX IF NOT EXISTS history_created_at ON history (created_at);
+    "#,
+    // 3: full-text index kept in sync by triggers.
+    r#"
+    CREATE VIRTUAL TABLE IF NOT EXISTS history_fts USING fts5(
+        text,
+        content = 'history',
+        content_rowid = 'id'
+    );
+    INSERT INTO history_fts (history_fts) VALUES ('rebuild');
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    END;
+    CREATE TRIGGER history_fts_update AFTER UPDATE OF text ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        INSERT INTO history_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    "#,
+    // 4: user edits, pins and tags.
+    r#"
+    ALTER TABLE history ADD COLUMN original_text TEXT;
+    ALTER TABLE history ADD COLUMN edited_at TEXT;
+    ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE IF NOT EXISTS history_tags (
+        entry_id INTEGER NOT NULL REFERENCES history (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (entry_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS history_tags_tag ON history_tags (tag);
+    "#,
+    // 5: store-wide flags and a per-row encryption flag; the full-text index
+    // is paused while encrypted.
+    r#"
+    CREATE TABLE IF NOT EXISTS history_meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ALTER TABLE history ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+    DROP TRIGGER history_fts_insert;
+    DROP TRIGGER history_fts_delete;
+    DROP TRIGGER history_fts_update;
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history
+    WHEN NOT EXISTS (SELECT 1 FROM history_meta WHERE key = 'encrypted') BEGIN
+        INSERT INTO history_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER history_fts_delete AFTER DELETE ON history
+    WHEN NOT EXISTS (SELECT 1 FROM history_meta WHERE key = 'encrypted') BEGIN
+        INSERT INTO history_fts (history_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    END;
+    CREATE TRIGGER history_fts_update AFTER UPDATE OF text ON history
+    WHEN NOT EXISTS (SELECT 1 FROM history_meta WHERE key = 'encrypted') BEGIN
+        INSERT INTO history_fts (history_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        INSERT INTO history_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    "#,
+];
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+const MAX_TAG_LEN: usize = 64;
+/// Oldest unpinned entries deleted per round while over the size limit.
+const SIZE_PURGE_BATCH: i64 = 200;
+/// Rows decrypted per round when searching an encrypted store.
+const SCAN_BATCH: i64 = 500;
+/// Rows decrypted per search request at most, so a rare term in a large
+/// encrypted store cannot stall the search; the page then ends early.
+const MAX_SCAN_ROWS: usize = 5_000;
+const SNIPPET_WORDS: usize = 16;
+
+const ENTRY_COLUMNS: &str = "h.id, h.text, h.created_at, h.duration_ms, h.model, \
+     h.language, h.sample_rate, h.input_device, h.paste_outcome, h.source_app, h.status, h.error, \
+     h.original_text, h.edited_at, h.pinned, h.encrypted, \
+     (SELECT json_group_array(tag) FROM \
+       (SELECT tag FROM history_tags t WHERE t.entry_id = h.id ORDER BY tag)) AS tags";
 
 #[derive(Debug, Serialize, FromRow)]
 pub struct HistoryEntry {
     pub id: i64,
     pub text: String,
     pub created_at: String,
+    pub duration_ms: Option<i64>,
+    pub model: Option<String>,
+    /// Language code the transcript was produced in; null when auto-detected.
+    pub language: Option<String>,
+    pub sample_rate: Option<i64>,
+    pub input_device: Option<String>,
+    /// `pasted`, `clipboard`, or null when nothing was output.
+    pub paste_outcome: Option<String>,
+    /// Application that had focus when the recording started.
+    pub source_app: Option<String>,
+    /// `ok` or `error`.
+    pub status: String,
+    pub error: Option<String>,
+    /// Transcript as first recorded; null until the text is edited.
+    pub original_text: Option<String>,
+    pub edited_at: Option<String>,
+    /// Pinned entries are exempt from retention purges.
+    pub pinned: bool,
+    /// Whether `text` and `original_text` are stored sealed; cleared once
+    /// `reveal` has decrypted them.
+    #[serde(skip)]
+    pub encrypted: bool,
+    #[sqlx(try_from = "String")]
+    pub tags: Tags,
+}
+
+/// Tags of an entry, sorted. Read from the `json_group_array` in `ENTRY_COLUMNS`.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<String>);
+
+impl TryFrom<String> for Tags {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&raw).map(Tags)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+}
+
+/// Filters for `search_history`. Dates compare against `created_at` (UTC,
+/// `YYYY-MM-DD[ HH:MM:SS]`); `from` is inclusive and `to` exclusive.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub search: Option<String>,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<i64>,
+    pub limit: Option<u32>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub model: Option<String>,
+    pub source_app: Option<String>,
+    pub status: Option<String>,
+    pub pinned: Option<bool>,
+    pub tag: Option<String>,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct HistoryHit {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub entry: HistoryEntry,
+    /// Matching excerpt as HTML: the text is escaped and matches are wrapped
+    /// in `<mark>`. Null without a search term.
+    pub snippet: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryHit>,
+    /// Set while more entries may match. A search of an encrypted store can
+    /// return a short or empty page with a cursor when it hit its scan limit.
+    pub next_cursor: Option<i64>,
+}
+
+/// A finished session as recorded by `stop_recording`, or an imported entry.
+#[derive(Debug, Default)]
+pub struct NewHistoryEntry {
+    pub text: String,
+    /// Defaults to now; set by imports to keep the original timestamp.
+    pub created_at: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub model: Option<String>,
+    pub language: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub input_device: Option<String>,
+    pub paste_outcome: Option<String>,
+    pub source_app: Option<String>,
+    pub error: Option<String>,
+    /// Set by imports of edited entries.
+    pub original_text: Option<String>,
+    pub edited_at: Option<String>,
+    pub pinned: bool,
+    pub tags: Vec<String>,
 }
 
 pub struct HistoryStore {
     pool: Pool<Sqlite>,
+    secrets: SecretStore,
+    /// Set while the store is encrypted. Regular operations hold a read lock;
+    /// enabling, disabling and rotating hold the write lock while they rewrite
+    /// every row.
+    cipher: tokio::sync::RwLock<Option<FieldCipher>>,
+    /// Why the key of an encrypted store could not be loaded at startup. The
+    /// store then stays usable for plaintext rows but refuses writes, since
+    /// they would be stored unencrypted.
+    locked: Option<String>,
 }
 
 impl HistoryStore {
-    pub async fn new() -> AppResult<Self> {
+    pub async fn new(secrets: SecretStore) -> AppResult<Self> {
         let dirs = ProjectDirs::from("com", "coolchatty", "CoolChatty")
             .ok_or_else(|| AppError::History("unable to locate data directory".into()))?;
         let db_path = dirs.data_local_dir().join("history.db");
@@ -42,36 +257,570 @@ impl HistoryStore {
             .connect_with(options)
             .await
             .map_err(|err| AppError::History(err.to_string()))?;
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS history (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                text TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            )
-            "#,
+        migrate(&pool).await?;
+        let (cipher, locked) = match load_cipher(&pool, &secrets).await {
+            Ok(cipher) => (cipher, None),
+            Err(err) => {
+                warn!(error = %err, "history key unavailable, encrypted entries stay locked");
+                (None, Some(err.to_string()))
+            }
+        };
+        Ok(Self {
+            pool,
+            secrets,
+            cipher: tokio::sync::RwLock::new(cipher),
+            locked,
+        })
+    }
+
+    /// Fails while the store is encrypted but its key could not be loaded.
+    fn ensure_unlocked(&self) -> AppResult<()> {
+        match &self.locked {
+            Some(reason) => Err(AppError::History(format!(
+                "history is encrypted but its key could not be loaded: {reason}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn add(&self, entry: &NewHistoryEntry) -> AppResult<()> {
+        self.ensure_unlocked()?;
+        let cipher = self.cipher.read().await;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        Self::insert(&mut conn, cipher.as_ref(), entry).await
+    }
+
+    async fn insert(
+        conn: &mut SqliteConnection,
+        cipher: Option<&FieldCipher>,
+        entry: &NewHistoryEntry,
+    ) -> AppResult<()> {
+        let status = if entry.error.is_some() { "error" } else { "ok" };
+        let text = seal(cipher, &entry.text)?;
+        let original_text = entry
+            .original_text
+            .as_deref()
+            .map(|original| seal(cipher, original))
+            .transpose()?;
+        let id = sqlx::query(
+            "INSERT INTO history (text, created_at, duration_ms, model, sample_rate, \
+             input_device, paste_outcome, source_app, status, error, original_text, edited_at, \
+             pinned, language, encrypted) \
+             VALUES (?1, COALESCE(datetime(?2), datetime('now')), ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, \
+             ?11, datetime(?12), ?13, ?14, ?15)",
         )
-        .execute(&pool)
+        .bind(text)
+        .bind(&entry.created_at)
+        .bind(entry.duration_ms)
+        .bind(&entry.model)
+        .bind(entry.sample_rate.map(i64::from))
+        .bind(&entry.input_device)
+        .bind(&entry.paste_outcome)
+        .bind(&entry.source_app)
+        .bind(status)
+        .bind(&entry.error)
+        .bind(original_text)
+        .bind(&entry.edited_at)
+        .bind(entry.pinned)
+        .bind(&entry.language)
+        .bind(cipher.is_some())
+        .execute(&mut *conn)
         .await
-        .map_err(|err| AppError::History(err.to_string()))?;
-        Ok(Self { pool })
+        .map_err(|err| AppError::History(err.to_string()))?
+        .last_insert_rowid();
+        if !entry.tags.is_empty() {
+            Self::replace_tags(conn, id, &normalize_tags(&entry.tags)).await?;
+        }
+        Ok(())
     }
 
-    pub async fn add(&self, text: &str) -> AppResult<()> {
-        sqlx::query("INSERT INTO history (text) VALUES (?1)")
-            .bind(text)
+    pub async fn get(&self, id: i64) -> AppResult<HistoryEntry> {
+        let cipher = self.cipher.read().await;
+        let mut entry = sqlx::query_as::<_, HistoryEntry>(&format!(
+            "SELECT {ENTRY_COLUMNS} FROM history h WHERE h.id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| AppError::History(err.to_string()))?
+        .ok_or_else(|| not_found(id))?;
+        reveal(cipher.as_ref(), &mut entry)?;
+        Ok(entry)
+    }
+
+    pub async fn delete(&self, id: i64) -> AppResult<()> {
+        let result = sqlx::query("DELETE FROM history WHERE id = ?")
+            .bind(id)
             .execute(&self.pool)
             .await
             .map_err(|err| AppError::History(err.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(not_found(id));
+        }
+        Ok(())
+    }
+
+    /// Replaces the text, keeping the first recorded version in
+    /// `original_text`. Editing back to the original clears the edit.
+    pub async fn update_text(&self, id: i64, text: &str) -> AppResult<HistoryEntry> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(AppError::Validation(
+                "transcript text cannot be empty".into(),
+            ));
+        }
+        self.ensure_unlocked()?;
+        {
+            let cipher = self.cipher.read().await;
+            let cipher = cipher.as_ref();
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            let (current, original, encrypted) =
+                sqlx::query_as::<_, (String, Option<String>, bool)>(
+                    "SELECT text, original_text, encrypted FROM history WHERE id = ?",
+                )
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?
+                .ok_or_else(|| not_found(id))?;
+            let first = match original {
+                Some(original) => open(cipher, encrypted, &original)?,
+                None => open(cipher, encrypted, &current)?,
+            };
+            let original_text = if first == text {
+                None
+            } else {
+                Some(seal(cipher, &first)?)
+            };
+            sqlx::query(
+                "UPDATE history SET text = ?1, original_text = ?2, \
+                 edited_at = CASE WHEN ?2 IS NULL THEN NULL ELSE datetime('now') END, \
+                 encrypted = ?3 WHERE id = ?4",
+            )
+            .bind(seal(cipher, text)?)
+            .bind(original_text)
+            .bind(cipher.is_some())
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+            tx.commit()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+        }
+        self.get(id).await
+    }
+
+    pub async fn set_pinned(&self, id: i64, pinned: bool) -> AppResult<HistoryEntry> {
+        let result = sqlx::query("UPDATE history SET pinned = ? WHERE id = ?")
+            .bind(pinned)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(not_found(id));
+        }
+        self.get(id).await
+    }
+
+    pub async fn set_tags(&self, id: i64, tags: &[String]) -> AppResult<HistoryEntry> {
+        let tags = normalize_tags(tags);
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        let exists = sqlx::query("SELECT 1 FROM history WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        if exists.is_none() {
+            return Err(not_found(id));
+        }
+        Self::replace_tags(&mut tx, id, &tags).await?;
+        tx.commit()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        self.get(id).await
+    }
+
+    /// Every tag in use, for autocompletion.
+    pub async fn tags(&self) -> AppResult<Vec<String>> {
+        sqlx::query_scalar::<_, String>("SELECT DISTINCT tag FROM history_tags ORDER BY tag")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))
+    }
+
+    async fn replace_tags(conn: &mut SqliteConnection, id: i64, tags: &[String]) -> AppResult<()> {
+        sqlx::query("DELETE FROM history_tags WHERE entry_id = ?")
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        for tag in tags {
+            sqlx::query("INSERT INTO history_tags (entry_id, tag) VALUES (?, ?)")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+        }
         Ok(())
     }
 
     pub async fn all(&self) -> AppResult<Vec<HistoryEntry>> {
-        sqlx::query_as::<_, HistoryEntry>(
-            "SELECT id, text, created_at FROM history ORDER BY id DESC",
+        let cipher = self.cipher.read().await;
+        let mut entries = sqlx::query_as::<_, HistoryEntry>(&format!(
+            "SELECT {ENTRY_COLUMNS} FROM history h ORDER BY h.id DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| AppError::History(err.to_string()))?;
+        for entry in &mut entries {
+            reveal(cipher.as_ref(), entry)?;
+        }
+        Ok(entries)
+    }
+
+    pub async fn search(&self, query: &HistoryQuery) -> AppResult<HistoryPage> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as usize;
+        let cipher = self.cipher.read().await;
+        let terms = search_terms(query.search.as_deref().unwrap_or_default());
+
+        let (mut entries, resume_cursor) = match cipher.as_ref() {
+            Some(cipher) if !terms.is_empty() => {
+                self.scan_encrypted(query, limit, cipher, &terms).await?
+            }
+            _ => {
+                let match_expr = fts_match_expression(&terms);
+                let mut builder = QueryBuilder::<Sqlite>::new(format!(
+                    "SELECT {ENTRY_COLUMNS}, NULL AS snippet FROM history h"
+                ));
+                // The snippet is built from the text below, not with FTS
+                // `snippet()`, whose output is not HTML-escaped
+                if match_expr.is_some() {
+                    builder.push(" JOIN history_fts ON history_fts.rowid = h.id");
+                }
+                builder.push(" WHERE 1 = 1");
+                if let Some(expr) = match_expr {
+                    builder.push(" AND history_fts MATCH ").push_bind(expr);
+                }
+                push_filters(&mut builder, query, query.cursor);
+                // One extra row tells us whether another page exists.
+                builder.push(" LIMIT ").push_bind(limit as i64 + 1);
+                let mut hits = builder
+                    .build_query_as::<HistoryHit>()
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(|err| AppError::History(err.to_string()))?;
+                for hit in &mut hits {
+                    reveal(cipher.as_ref(), &mut hit.entry)?;
+                    if !terms.is_empty() {
+                        hit.snippet = Some(highlight(&hit.entry.text, &terms));
+                    }
+                }
+                (hits, None)
+            }
+        };
+
+        let next_cursor = if entries.len() > limit {
+            entries.truncate(limit);
+            entries.last().map(|hit| hit.entry.id)
+        } else {
+            resume_cursor
+        };
+        Ok(HistoryPage {
+            entries,
+            next_cursor,
+        })
+    }
+
+    /// Search fallback while encrypted: the full-text index holds nothing, so
+    /// rows matching the other filters are decrypted and matched here until
+    /// `limit + 1` hits are found. Each call decrypts at most `MAX_SCAN_ROWS`
+    /// rows and then returns the cursor to resume from, so searching a large
+    /// encrypted history takes several requests where FTS would need one.
+    async fn scan_encrypted(
+        &self,
+        query: &HistoryQuery,
+        limit: usize,
+        cipher: &FieldCipher,
+        terms: &[String],
+    ) -> AppResult<(Vec<HistoryHit>, Option<i64>)> {
+        let mut hits = Vec::new();
+        let mut cursor = query.cursor;
+        let mut scanned = 0;
+        loop {
+            if scanned >= MAX_SCAN_ROWS {
+                return Ok((hits, cursor));
+            }
+            let mut builder = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {ENTRY_COLUMNS}, NULL AS snippet FROM history h WHERE 1 = 1"
+            ));
+            push_filters(&mut builder, query, cursor);
+            builder.push(" LIMIT ").push_bind(SCAN_BATCH);
+            let batch = builder
+                .build_query_as::<HistoryHit>()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            let exhausted = (batch.len() as i64) < SCAN_BATCH;
+            scanned += batch.len();
+            cursor = batch.last().map(|hit| hit.entry.id);
+            for mut hit in batch {
+                reveal(Some(cipher), &mut hit.entry)?;
+                if let Some(snippet) = match_snippet(&hit.entry.text, terms) {
+                    hit.snippet = Some(snippet);
+                    hits.push(hit);
+                    if hits.len() > limit {
+                        return Ok((hits, None));
+                    }
+                }
+            }
+            if exhausted {
+                return Ok((hits, None));
+            }
+        }
+    }
+
+    /// `(text, created_at)` of every stored entry, used to dedupe imports.
+    pub async fn fingerprints(&self) -> AppResult<Vec<(String, String)>> {
+        let cipher = self.cipher.read().await;
+        let rows = sqlx::query_as::<_, (String, String, bool)>(
+            "SELECT text, created_at, encrypted FROM history",
         )
         .fetch_all(&self.pool)
         .await
+        .map_err(|err| AppError::History(err.to_string()))?;
+        rows.into_iter()
+            .map(|(text, created_at, encrypted)| {
+                Ok((open(cipher.as_ref(), encrypted, &text)?, created_at))
+            })
+            .collect()
+    }
+
+    /// Inserts all entries in one transaction.
+    pub async fn add_many(&self, entries: &[NewHistoryEntry]) -> AppResult<()> {
+        self.ensure_unlocked()?;
+        let cipher = self.cipher.read().await;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        for entry in entries {
+            Self::insert(&mut tx, cipher.as_ref(), entry).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))
+    }
+
+    pub async fn is_encrypted(&self) -> bool {
+        self.locked.is_some() || self.cipher.read().await.is_some()
+    }
+
+    /// Encrypts every stored transcript with a new key kept in the credential
+    /// store, or decrypts them all again. The full-text index is emptied while
+    /// encrypted and rebuilt on decryption.
+    pub async fn set_encryption(&self, enabled: bool) -> AppResult<()> {
+        self.ensure_unlocked()?;
+        let mut guard = self.cipher.write().await;
+        if guard.is_some() == enabled {
+            return Ok(());
+        }
+        if enabled {
+            let (cipher, key) = FieldCipher::generate();
+            self.store_secret(HISTORY_KEY_SECRET, Some(key)).await?;
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            // Flag first so the triggers leave the index alone during the rewrite
+            for sql in [
+                "INSERT OR REPLACE INTO history_meta (key, value) VALUES ('encrypted', '1')",
+                "INSERT INTO history_fts (history_fts) VALUES ('delete-all')",
+            ] {
+                sqlx::query(sql)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|err| AppError::History(err.to_string()))?;
+            }
+            rewrite_rows(&mut tx, None, Some(&cipher)).await?;
+            tx.commit()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            *guard = Some(cipher);
+        } else {
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            rewrite_rows(&mut tx, guard.as_ref(), None).await?;
+            for sql in [
+                "DELETE FROM history_meta WHERE key = 'encrypted'",
+                "INSERT INTO history_fts (history_fts) VALUES ('rebuild')",
+            ] {
+                sqlx::query(sql)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|err| AppError::History(err.to_string()))?;
+            }
+            tx.commit()
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+            *guard = None;
+            self.store_secret(HISTORY_KEY_SECRET, None).await?;
+        }
+        self.compact().await?;
+        info!(enabled, "changed history encryption");
+        Ok(())
+    }
+
+    /// Re-encrypts every transcript under a fresh key. The new key is stored
+    /// under a staging name first so an interrupted rotation can be finished
+    /// on the next start.
+    pub async fn rotate_key(&self) -> AppResult<()> {
+        self.ensure_unlocked()?;
+        let mut guard = self.cipher.write().await;
+        let Some(current) = guard.as_ref() else {
+            return Err(AppError::Validation(
+                "history encryption is not enabled".into(),
+            ));
+        };
+        let (next, key) = FieldCipher::generate();
+        self.store_secret(HISTORY_KEY_NEXT_SECRET, Some(key.clone()))
+            .await?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        rewrite_rows(&mut tx, Some(current), Some(&next)).await?;
+        if let Err(err) = tx.commit().await {
+            let _ = self.store_secret(HISTORY_KEY_NEXT_SECRET, None).await;
+            return Err(AppError::History(err.to_string()));
+        }
+        *guard = Some(next);
+        self.store_secret(HISTORY_KEY_SECRET, Some(key)).await?;
+        self.store_secret(HISTORY_KEY_NEXT_SECRET, None).await?;
+        self.compact().await?;
+        info!("rotated history encryption key");
+        Ok(())
+    }
+
+    async fn store_secret(&self, name: &'static str, value: Option<String>) -> AppResult<()> {
+        let secrets = self.secrets.clone();
+        tokio::task::spawn_blocking(move || match value {
+            Some(value) => secrets.set(name, &value),
+            None => secrets.delete(name),
+        })
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?
+    }
+
+    /// Drops free pages that may still hold old row versions.
+    async fn compact(&self) -> AppResult<()> {
+        for sql in ["VACUUM", "PRAGMA wal_checkpoint(TRUNCATE)"] {
+            sqlx::query(sql)
+                .execute(&self.pool)
+                .await
+                .map_err(|err| AppError::History(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Deletes unpinned entries beyond the policy limits, oldest first, and
+    /// vacuums the database when anything was removed. Returns the number of
+    /// deleted entries.
+    pub async fn apply_retention(&self, policy: &RetentionPolicy) -> AppResult<u64> {
+        let mut deleted = 0;
+        if let Some(days) = policy.max_age_days {
+            deleted += self
+                .execute_purge(
+                    sqlx::query(
+                        "DELETE FROM history WHERE pinned = 0 \
+                         AND created_at < datetime('now', '-' || ? || ' days')",
+                    )
+                    .bind(i64::from(days)),
+                )
+                .await?;
+        }
+        if let Some(max) = policy.max_entries {
+            deleted += self
+                .execute_purge(
+                    sqlx::query(
+                        "DELETE FROM history WHERE pinned = 0 AND id NOT IN \
+                         (SELECT id FROM history WHERE pinned = 0 ORDER BY id DESC LIMIT ?)",
+                    )
+                    .bind(i64::from(max)),
+                )
+                .await?;
+        }
+        if let Some(max_mb) = policy.max_db_size_mb {
+            let max_bytes = i64::from(max_mb) * 1024 * 1024;
+            while self.live_size().await? > max_bytes {
+                let removed = self
+                    .execute_purge(
+                        sqlx::query(
+                            "DELETE FROM history WHERE id IN \
+                             (SELECT id FROM history WHERE pinned = 0 ORDER BY id LIMIT ?)",
+                        )
+                        .bind(SIZE_PURGE_BATCH),
+                    )
+                    .await?;
+                if removed == 0 {
+                    warn!(
+                        max_mb,
+                        "history over size limit but only pinned entries remain"
+                    );
+                    break;
+                }
+                deleted += removed;
+            }
+        }
+        if deleted > 0 {
+            self.compact().await?;
+            info!(deleted, "applied history retention");
+        }
+        Ok(deleted)
+    }
+
+    async fn execute_purge<'q>(
+        &self,
+        query: sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>,
+    ) -> AppResult<u64> {
+        query
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| AppError::History(err.to_string()))
+    }
+
+    /// Bytes in use by the database, excluding free pages a VACUUM would drop.
+    async fn live_size(&self) -> AppResult<i64> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT (page_count - freelist_count) * page_size \
+             FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+        )
+        .fetch_one(&self.pool)
+        .await
         .map_err(|err| AppError::History(err.to_string()))
     }
 
@@ -83,3 +832,369 @@ impl HistoryStore {
         Ok(())
     }
 }
+
+fn search_terms(input: &str) -> Vec<String> {
+    input.split_whitespace().map(str::to_string).collect()
+}
+
+/// Turns search terms into an FTS5 query: every word must match, as a
+/// prefix, and FTS operators in the input are treated as plain text.
+fn fts_match_expression(terms: &[String]) -> Option<String> {
+    if terms.is_empty() {
+        return None;
+    }
+    let quoted: Vec<String> = terms
+        .iter()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    Some(quoted.join(" "))
+}
+
+/// Filters, cursor and ordering shared by the indexed and scanning searches.
+fn push_filters(builder: &mut QueryBuilder<'_, Sqlite>, query: &HistoryQuery, cursor: Option<i64>) {
+    if let Some(cursor) = cursor {
+        let op = match query.order {
+            SortOrder::NewestFirst => " AND h.id < ",
+            SortOrder::OldestFirst => " AND h.id > ",
+        };
+        builder.push(op).push_bind(cursor);
+    }
+    if let Some(from) = non_empty(&query.from) {
+        builder
+            .push(" AND h.created_at >= datetime(")
+            .push_bind(from)
+            .push(")");
+    }
+    if let Some(to) = non_empty(&query.to) {
+        builder
+            .push(" AND h.created_at < datetime(")
+            .push_bind(to)
+            .push(")");
+    }
+    if let Some(model) = non_empty(&query.model) {
+        builder.push(" AND h.model = ").push_bind(model);
+    }
+    if let Some(app) = non_empty(&query.source_app) {
+        builder.push(" AND h.source_app = ").push_bind(app);
+    }
+    if let Some(status) = non_empty(&query.status) {
+        builder.push(" AND h.status = ").push_bind(status);
+    }
+    if let Some(pinned) = query.pinned {
+        builder.push(" AND h.pinned = ").push_bind(pinned);
+    }
+    if let Some(tag) = non_empty(&query.tag) {
+        builder
+            .push(" AND EXISTS (SELECT 1 FROM history_tags t WHERE t.entry_id = h.id AND t.tag = ")
+            .push_bind(tag)
+            .push(")");
+    }
+    builder.push(match query.order {
+        SortOrder::NewestFirst => " ORDER BY h.id DESC",
+        SortOrder::OldestFirst => " ORDER BY h.id ASC",
+    });
+}
+
+/// Mirrors the FTS query for decrypted text: every term must be a
+/// case-insensitive prefix of some word. Returns the highlighted excerpt.
+fn match_snippet(text: &str, terms: &[String]) -> Option<String> {
+    let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+    let all_match = terms.iter().all(|term| {
+        text.split_whitespace()
+            .any(|word| word_matches(word, std::slice::from_ref(term)))
+    });
+    all_match.then(|| highlight(text, &terms))
+}
+
+/// An HTML excerpt of `text` around the first word matching `terms`, with
+/// the text escaped and the matching words wrapped in `<mark>`.
+fn highlight(text: &str, terms: &[String]) -> String {
+    let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let first = words
+        .iter()
+        .position(|word| word_matches(word, &terms))
+        .unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_WORDS / 2);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let mut snippet: Vec<String> = words[start..end]
+        .iter()
+        .map(|word| {
+            if word_matches(word, &terms) {
+                format!("<mark>{}</mark>", escape_html(word))
+            } else {
+                escape_html(word)
+            }
+        })
+        .collect();
+    if start > 0 {
+        snippet.insert(0, "…".into());
+    }
+    if end < words.len() {
+        snippet.push("…".into());
+    }
+    snippet.join(" ")
+}
+
+/// Whether `word` starts with one of the lowercase `terms`, ignoring leading
+/// punctuation.
+fn word_matches(word: &str, terms: &[String]) -> bool {
+    let word = word.to_lowercase();
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    terms.iter().any(|term| word.starts_with(term.as_str()))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn seal(cipher: Option<&FieldCipher>, text: &str) -> AppResult<String> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(text),
+        None => Ok(text.to_string()),
+    }
+}
+
+/// Returns the plaintext of a column value from a row whose `encrypted` flag
+/// is `encrypted`.
+fn open(cipher: Option<&FieldCipher>, encrypted: bool, stored: &str) -> AppResult<String> {
+    match (encrypted, cipher) {
+        (false, _) => Ok(stored.to_string()),
+        (true, Some(cipher)) => cipher.decrypt(stored),
+        (true, None) => Err(AppError::History(
+            "history entry is encrypted but no key is loaded".into(),
+        )),
+    }
+}
+
+fn reveal(cipher: Option<&FieldCipher>, entry: &mut HistoryEntry) -> AppResult<()> {
+    entry.text = open(cipher, entry.encrypted, &entry.text)?;
+    if let Some(original) = entry.original_text.take() {
+        entry.original_text = Some(open(cipher, entry.encrypted, &original)?);
+    }
+    entry.encrypted = false;
+    Ok(())
+}
+
+/// Decrypts every transcript with `from` and re-encrypts it with `to`; `None`
+/// on either side means plaintext.
+async fn rewrite_rows(
+    conn: &mut SqliteConnection,
+    from: Option<&FieldCipher>,
+    to: Option<&FieldCipher>,
+) -> AppResult<()> {
+    let rows = sqlx::query_as::<_, (i64, String, Option<String>, bool)>(
+        "SELECT id, text, original_text, encrypted FROM history",
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|err| AppError::History(err.to_string()))?;
+    for (id, text, original, encrypted) in rows {
+        let text = seal(to, &open(from, encrypted, &text)?)?;
+        let original = original
+            .map(|original| seal(to, &open(from, encrypted, &original)?))
+            .transpose()?;
+        sqlx::query("UPDATE history SET text = ?, original_text = ?, encrypted = ? WHERE id = ?")
+            .bind(text)
+            .bind(original)
+            .bind(to.is_some())
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Loads the history key when the database is marked encrypted, finishing a
+/// key rotation that was interrupted after its transaction committed.
+async fn load_cipher(pool: &Pool<Sqlite>, secrets: &SecretStore) -> AppResult<Option<FieldCipher>> {
+    let encrypted = sqlx::query("SELECT 1 FROM history_meta WHERE key = 'encrypted'")
+        .fetch_optional(pool)
+        .await
+        .map_err(|err| AppError::History(err.to_string()))?
+        .is_some();
+    if !encrypted {
+        return Ok(None);
+    }
+
+    let store = secrets.clone();
+    let (current, staged) = tokio::task::spawn_blocking(move || {
+        Ok::<_, AppError>((
+            store.get(HISTORY_KEY_SECRET)?,
+            store.get(HISTORY_KEY_NEXT_SECRET)?,
+        ))
+    })
+    .await
+    .map_err(|err| AppError::Internal(err.to_string()))??;
+
+    let sample: Option<String> =
+        sqlx::query_scalar("SELECT text FROM history WHERE encrypted = 1 LIMIT 1")
+            .fetch_optional(pool)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+    let decrypts = |cipher: &FieldCipher| {
+        sample
+            .as_deref()
+            .is_none_or(|sample| cipher.decrypt(sample).is_ok())
+    };
+
+    if let Some(staged) = staged {
+        let next = FieldCipher::from_encoded(&staged)?;
+        let promote = decrypts(&next)
+            && !current
+                .as_deref()
+                .map(FieldCipher::from_encoded)
+                .transpose()?
+                .is_some_and(|current| decrypts(&current));
+        let store = secrets.clone();
+        tokio::task::spawn_blocking(move || {
+            if promote {
+                store.set(HISTORY_KEY_SECRET, &staged)?;
+            }
+            store.delete(HISTORY_KEY_NEXT_SECRET)
+        })
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))??;
+        if promote {
+            warn!("finished interrupted history key rotation");
+            return Ok(Some(next));
+        }
+    }
+
+    let key = current.ok_or_else(|| {
+        AppError::History(
+            "history is encrypted but its key is missing from the credential store".into(),
+        )
+    })?;
+    let cipher = FieldCipher::from_encoded(&key)?;
+    if !decrypts(&cipher) {
+        return Err(AppError::History(
+            "history key does not match the encrypted database".into(),
+        ));
+    }
+    Ok(Some(cipher))
+}
+
+/// Trims tags, splits comma-separated input, drops empties and duplicates,
+/// and caps each tag at `MAX_TAG_LEN` characters.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = tags
+        .iter()
+        .flat_map(|tag| tag.split(','))
+        .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.chars().take(MAX_TAG_LEN).collect())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::History(format!("history entry {id} not found"))
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+async fn migrate(pool: &Pool<Sqlite>) -> AppResult<()> {
+    let current: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await
+        .map_err(|err| AppError::History(err.to_string()))?;
+    if current as usize > MIGRATIONS.len() {
+        return Err(AppError::History(format!(
+            "history database version {current} is newer than this build supports"
+        )));
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index + 1;
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        sqlx::raw_sql(sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| AppError::History(format!("migration {version} failed: {err}")))?;
+        // PRAGMA does not accept bound parameters.
+        sqlx::query(&format!("PRAGMA user_version = {version}"))
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|err| AppError::History(err.to_string()))?;
+        info!(version, "migrated history database");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(input: &str) -> Vec<String> {
+        search_terms(input)
+    }
+
+    #[test]
+    fn snippet_escapes_markup_in_the_transcript() {
+        let text = "say <img src=x onerror=alert(1)> & \"quote\" 'it' hello world";
+        let snippet = highlight(text, &terms("hello"));
+        assert_eq!(
+            snippet,
+            "say &lt;img src=x onerror=alert(1)&gt; &amp; &quot;quote&quot; &#39;it&#39; \
+             <mark>hello</mark> world"
+        );
+        assert!(!snippet.contains("<img"));
+    }
+
+    #[test]
+    fn snippet_escapes_a_matching_word() {
+        // Leading punctuation is skipped when matching, as FTS does
+        let snippet = match_snippet("a <script>alert(1)</script> b", &terms("script"));
+        assert_eq!(
+            snippet.as_deref(),
+            Some("a <mark>&lt;script&gt;alert(1)&lt;/script&gt;</mark> b")
+        );
+    }
+
+    #[test]
+    fn snippet_requires_every_term() {
+        assert_eq!(
+            match_snippet("hello world", &terms("hel wor")).as_deref(),
+            Some("<mark>hello</mark> <mark>world</mark>")
+        );
+        assert_eq!(match_snippet("hello world", &terms("hel xyz")), None);
+    }
+
+    #[test]
+    fn snippet_is_an_excerpt_around_the_first_match() {
+        let text: Vec<String> = (0..40).map(|n| format!("w{n}")).collect();
+        let mut text = text.join(" ");
+        text.push_str(" needle");
+        let snippet = highlight(&text, &terms("needle"));
+        assert!(snippet.starts_with("… "));
+        assert!(snippet.ends_with("<mark>needle</mark>"));
+        assert_eq!(snippet.split(' ').count(), 1 + SNIPPET_WORDS / 2 + 1);
+    }
+}
@@ -1,20 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use parking_lot::Mutex;
 use tauri::{AppHandle, Emitter};
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+use tokio::sync::Mutex as AsyncMutex;
 use tracing::{debug, warn};
 
+use crate::commands;
 use crate::errors::{AppError, AppResult};
+use crate::settings::HotkeyMode;
 
 const EVENT_NAME: &str = "trigger_record";
+const PTT_MIN_HOLD: Duration = Duration::from_millis(180);
+
+/// Tracks the physical key state for push-to-talk so auto-repeat presses are
+/// ignored and short taps never start a session.
+#[derive(Default)]
+struct PushToTalkState {
+    held: bool,
+    active: bool,
+    generation: u64,
+}
 
 pub struct HotkeyManager {
     binding: Mutex<String>,
+    mode: Mutex<HotkeyMode>,
+    ptt: Arc<Mutex<PushToTalkState>>,
+    /// Held while a push-to-talk start or stop runs, so a release during a
+    /// slow start stops the session once it is up.
+    turn: Arc<AsyncMutex<()>>,
 }
 
 impl HotkeyManager {
-    pub fn new(app: &AppHandle, default: &str) -> AppResult<Self> {
+    pub fn new(app: &AppHandle, default: &str, mode: HotkeyMode) -> AppResult<Self> {
         let manager = Self {
             binding: Mutex::new(String::new()),
+            mode: Mutex::new(mode),
+            ptt: Arc::new(Mutex::new(PushToTalkState::default())),
+            turn: Arc::new(AsyncMutex::new(())),
         };
         manager.register_binding(app, default)?;
         Ok(manager)
@@ -24,7 +48,8 @@ impl HotkeyManager {
         self.binding.lock().clone()
     }
 
-    pub fn update(&self, app: &AppHandle, binding: String) -> AppResult<()> {
+    pub fn update(&self, app: &AppHandle, binding: String, mode: HotkeyMode) -> AppResult<()> {
+        *self.mode.lock() = mode;
         self.register_binding(app, &binding)
     }
 
@@ -37,6 +62,7 @@ impl HotkeyManager {
         let normalized = normalize_binding(binding.trim());
         let shortcut_manager = app.global_shortcut();
         shortcut_manager.unregister_all().map_err(AppError::from)?;
+        *self.ptt.lock() = PushToTalkState::default();
 
         if normalized.is_empty() {
             warn!("Hotkey cleared; no global shortcut registered");
@@ -44,23 +70,92 @@ impl HotkeyManager {
             return Ok(());
         }
 
-        let binding_owned = normalized;
+        let binding_owned = normalized.clone();
         let payload_binding = binding_owned.clone();
-        let event_name = EVENT_NAME.to_string();
-        shortcut_manager
-            .on_shortcut(normalized, move |app, _, event| {
-                if matches!(event.state, ShortcutState::Pressed) {
-                    let _ = app.emit(&event_name, payload_binding.clone());
-                }
-            })
-            .map_err(AppError::from)?;
+        let mode = *self.mode.lock();
+        match mode {
+            HotkeyMode::Toggle => {
+                let event_name = EVENT_NAME.to_string();
+                shortcut_manager
+                    .on_shortcut(normalized.as_str(), move |app, _, event| {
+                        if matches!(event.state, ShortcutState::Pressed) {
+                            let _ = app.emit(&event_name, payload_binding.clone());
+                        }
+                    })
+                    .map_err(AppError::from)?;
+            }
+            HotkeyMode::PushToTalk => {
+                let ptt = self.ptt.clone();
+                let turn = self.turn.clone();
+                shortcut_manager
+                    .on_shortcut(normalized.as_str(), move |app, _, event| {
+                        match event.state {
+                            ShortcutState::Pressed => on_ptt_pressed(app, &ptt, &turn),
+                            ShortcutState::Released => on_ptt_released(app, &ptt, &turn),
+                        }
+                    })
+                    .map_err(AppError::from)?;
+            }
+        }
 
         *self.binding.lock() = binding_owned.clone();
-        debug!(shortcut = %binding_owned, "registered global hotkey");
+        debug!(shortcut = %binding_owned, ?mode, "registered global hotkey");
         Ok(())
     }
 }
 
+/// Starts recording once the key has been held for `PTT_MIN_HOLD`. The
+/// recorder is driven directly rather than through a frontend listener.
+fn on_ptt_pressed(app: &AppHandle, ptt: &Arc<Mutex<PushToTalkState>>, turn: &Arc<AsyncMutex<()>>) {
+    let generation = {
+        let mut state = ptt.lock();
+        if state.held {
+            // OS key auto-repeat delivers further presses while held
+            return;
+        }
+        state.held = true;
+        state.active = false;
+        state.generation = state.generation.wrapping_add(1);
+        state.generation
+    };
+
+    let app = app.clone();
+    let ptt = ptt.clone();
+    let turn = turn.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(PTT_MIN_HOLD).await;
+        // Taken before `active` is set: the release only queues a stop for
+        // active sessions, and that stop then waits for this start
+        let _turn = turn.lock().await;
+        {
+            let mut state = ptt.lock();
+            if !state.held || state.generation != generation {
+                return;
+            }
+            state.active = true;
+        }
+        commands::push_to_talk_start(&app).await;
+    });
+}
+
+fn on_ptt_released(app: &AppHandle, ptt: &Arc<Mutex<PushToTalkState>>, turn: &Arc<AsyncMutex<()>>) {
+    let was_active = {
+        let mut state = ptt.lock();
+        state.held = false;
+        std::mem::take(&mut state.active)
+    };
+    if was_active {
+        let app = app.clone();
+        let turn = turn.clone();
+        tauri::async_runtime::spawn(async move {
+            let _turn = turn.lock().await;
+            commands::push_to_talk_stop(&app).await;
+        });
+    } else {
+        debug!("push-to-talk tap shorter than minimum hold, ignoring");
+    }
+}
+
 fn normalize_binding(binding: &str) -> String {
     if binding.is_empty() {
         return String::new();
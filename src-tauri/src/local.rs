@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tracing::info;
+
+use crate::dsp::Resampler;
+use crate::errors::{AppError, AppResult};
+use crate::queue::AudioReceiver;
+use crate::transcription::{SessionContext, TranscriptUpdate, TranscriptionBackend};
+
+/// Whisper models are trained on 16 kHz mono audio.
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+const MIN_RECORDING_MS: f32 = 200.0;
+const UNAVAILABLE: &str =
+    "this build does not include local transcription (enable the `local-whisper` feature)";
+
+/// Runs a Whisper-family model on the CPU without any network access. Audio is
+/// buffered for the whole session and decoded once the recorder stops.
+pub struct LocalWhisperBackend {
+    model_path: PathBuf,
+    language: Option<String>,
+}
+
+impl LocalWhisperBackend {
+    /// A blank `language` lets Whisper detect it, like `auto`.
+    pub fn new(model_path: PathBuf, language: Option<String>) -> Self {
+        Self {
+            model_path,
+            language: language
+                .map(|language| language.trim().to_string())
+                .filter(|language| !language.is_empty()),
+        }
+    }
+}
+
+impl TranscriptionBackend for LocalWhisperBackend {
+    fn name(&self) -> &'static str {
+        "local-whisper"
+    }
+
+    fn sample_rate(&self) -> u32 {
+        WHISPER_SAMPLE_RATE
+    }
+
+    fn transcribe(
+        self: Box<Self>,
+        audio_rx: AudioReceiver,
+        ctx: SessionContext,
+    ) -> BoxFuture<'static, AppResult<String>> {
+        async move {
+            let result = self.decode(audio_rx, ctx.sample_rate).await;
+            // Also after a failure, so the UI does not wait for a final state
+            let text = result.as_deref().unwrap_or_default().to_string();
+            (ctx.sink)(TranscriptUpdate {
+                session_id: ctx.session_id,
+                sequence: 1,
+                delta: text.clone(),
+                text: Some(text),
+                is_final: true,
+            });
+            let text = result?;
+            info!(length = text.len(), "local transcription completed");
+            Ok(text)
+        }
+        .boxed()
+    }
+}
+
+impl LocalWhisperBackend {
+    /// Buffers the session's audio and runs the model on it once the
+    /// recorder stops.
+    async fn decode(self, mut audio_rx: AudioReceiver, sample_rate: u32) -> AppResult<String> {
+        let mut samples: Vec<i16> = Vec::new();
+        while let Some(chunk) = audio_rx.recv().await {
+            samples.extend_from_slice(&chunk);
+        }
+
+        if samples.is_empty() {
+            return Err(AppError::AudioEmpty);
+        }
+        let total_ms = (samples.len() as f32 / sample_rate as f32) * 1000.0;
+        if total_ms < MIN_RECORDING_MS {
+            return Err(AppError::Validation(format!(
+                "Recording too short (only {total_ms:.1} ms). Please speak a bit longer."
+            )));
+        }
+
+        let audio = to_whisper_input(&samples, sample_rate);
+        let model_path = self.model_path;
+        let language = self.language;
+        let text = tokio::task::spawn_blocking(move || {
+            run_whisper(&model_path, language.as_deref(), &audio)
+        })
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))??;
+
+        if text.trim().is_empty() {
+            return Err(AppError::LocalEngine(
+                "No transcript produced by local model".into(),
+            ));
+        }
+        Ok(text)
+    }
+}
+
+/// Fails when this build cannot transcribe locally, so that the engine is
+/// refused before anything is recorded.
+pub fn ensure_available() -> AppResult<()> {
+    if cfg!(feature = "local-whisper") {
+        Ok(())
+    } else {
+        Err(AppError::LocalEngine(UNAVAILABLE.into()))
+    }
+}
+
+/// Converts PCM16 into the normalized 16 kHz floats Whisper expects. The
+/// recorder already delivers 16 kHz; other rates go through the resampler.
+fn to_whisper_input(samples: &[i16], sample_rate: u32) -> Vec<f32> {
+    let normalized: Vec<f32> = samples
+        .iter()
+        .map(|sample| *sample as f32 / i16::MAX as f32)
+        .collect();
+    if sample_rate == WHISPER_SAMPLE_RATE {
+        return normalized;
+    }
+    let mut resampler = Resampler::new(sample_rate, WHISPER_SAMPLE_RATE);
+    let mut output = Vec::new();
+    resampler.process(&normalized, &mut output);
+    resampler.flush(&mut output);
+    output
+}
+
+/// The model of the last session, kept loaded so that later sessions with
+/// the same file skip reading it again.
+#[cfg(feature = "local-whisper")]
+static MODEL: parking_lot::Mutex<Option<(PathBuf, std::sync::Arc<whisper_rs::WhisperContext>)>> =
+    parking_lot::Mutex::new(None);
+
+#[cfg(feature = "local-whisper")]
+fn load_model(model_path: &Path) -> AppResult<std::sync::Arc<whisper_rs::WhisperContext>> {
+    use whisper_rs::{WhisperContext, WhisperContextParameters};
+
+    let mut cached = MODEL.lock();
+    if let Some((path, context)) = cached.as_ref() {
+        if path == model_path {
+            return Ok(context.clone());
+        }
+    }
+    // Release the previous model before loading the next one
+    *cached = None;
+    let path = model_path
+        .to_str()
+        .ok_or_else(|| AppError::LocalEngine("model path is not valid UTF-8".into()))?;
+    let context = WhisperContext::new_with_params(path, WhisperContextParameters::default())
+        .map_err(|err| AppError::LocalEngine(format!("failed to load model: {err}")))?;
+    let context = std::sync::Arc::new(context);
+    *cached = Some((model_path.to_path_buf(), context.clone()));
+    info!(path = %model_path.display(), "local model loaded");
+    Ok(context)
+}
+
+#[cfg(feature = "local-whisper")]
+fn run_whisper(model_path: &Path, language: Option<&str>, audio: &[f32]) -> AppResult<String> {
+    use whisper_rs::{FullParams, SamplingStrategy};
+
+    let context = load_model(model_path)?;
+    let mut state = context
+        .create_state()
+        .map_err(|err| AppError::LocalEngine(err.to_string()))?;
+
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get().min(8) as i32)
+        .unwrap_or(4);
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_n_threads(threads);
+    params.set_language(Some(language.unwrap_or("auto")));
+    params.set_translate(false);
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+
+    state
+        .full(params, audio)
+        .map_err(|err| AppError::LocalEngine(err.to_string()))?;
+    let segments = state
+        .full_n_segments()
+        .map_err(|err| AppError::LocalEngine(err.to_string()))?;
+    let mut text = String::new();
+    for index in 0..segments {
+        let segment = state
+            .full_get_segment_text(index)
+            .map_err(|err| AppError::LocalEngine(err.to_string()))?;
+        text.push_str(segment.trim_end());
+    }
+    Ok(text.trim().to_string())
+}
+
+#[cfg(not(feature = "local-whisper"))]
+fn run_whisper(_model_path: &Path, _language: Option<&str>, _audio: &[f32]) -> AppResult<String> {
+    Err(AppError::LocalEngine(UNAVAILABLE.into()))
+}
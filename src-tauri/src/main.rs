@@ -1,14 +1,27 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod audio;
+mod cipher;
 mod commands;
+mod devices;
+mod dsp;
 mod errors;
+mod export;
+mod fsutil;
 mod history;
 mod hotkey;
+mod local;
+mod meter;
 mod paste;
+mod queue;
 mod realtime;
+mod retention;
+mod sample;
+mod secrets;
 mod settings;
 mod state;
+mod transcription;
+mod vad;
 
 use audio::RecorderService;
 use history::HistoryStore;
@@ -23,16 +36,31 @@ fn main() {
     init_tracing();
 
     tauri::Builder::default()
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .invoke_handler(tauri::generate_handler![
             commands::start_recording,
             commands::stop_recording,
             commands::get_history,
+            commands::search_history,
+            commands::delete_history_entry,
+            commands::update_history_text,
+            commands::set_history_pinned,
+            commands::set_history_tags,
+            commands::list_history_tags,
+            commands::export_history,
+            commands::import_history,
             commands::clear_history,
+            commands::get_history_encryption,
+            commands::set_history_encryption,
+            commands::rotate_history_key,
             commands::recorder_status,
+            commands::list_input_devices,
+            commands::warm_mic_status,
             commands::trigger_record_event,
             commands::get_settings,
             commands::save_settings,
+            commands::clear_api_key,
         ])
         .setup(|app| {
             let recorder = RecorderService::new();
@@ -41,11 +69,19 @@ fn main() {
             let initial_settings = tauri::async_runtime::block_on(settings_store.get());
             let hotkeys = {
                 let handle = app.handle();
-                HotkeyManager::new(&handle, &initial_settings.hotkey)?
+                HotkeyManager::new(
+                    handle,
+                    &initial_settings.hotkey,
+                    initial_settings.hotkey_mode,
+                )?
             };
-            let history = tauri::async_runtime::block_on(HistoryStore::new())?;
+            let history =
+                tauri::async_runtime::block_on(HistoryStore::new(settings_store.secrets()))?;
 
             let state = AppState::new(recorder, history, paste, hotkeys, settings_store);
+            retention::spawn(state.history.clone(), state.settings.clone());
+            devices::spawn_monitor(app.handle().clone(), state.recorder.clone());
+            commands::sync_warm_mic(app.handle(), &state.recorder, &initial_settings);
             app.manage(state);
             Ok(())
         })
@@ -0,0 +1,338 @@
+use serde::Serialize;
+
+/// How often a level reading is reported; fast enough for a VU meter without
+/// flooding the webview with events.
+const LEVEL_INTERVAL_MS: u32 = 100;
+/// Window over which clipping and DC offset are judged.
+const ANALYSIS_WINDOW_MS: u32 = 1_000;
+/// Readings quieter than this count as silence. A muted or disconnected input
+/// sits well below it, while a quiet room does not.
+const SILENCE_DBFS: f32 = -60.0;
+/// Continuous silence before the user is warned.
+const SILENCE_WARNING_MS: u32 = 3_000;
+/// Samples at or above this magnitude saturate PCM16.
+const CLIP_LEVEL: f32 = 0.999;
+/// Share of clipped samples in a window that triggers a warning.
+const CLIP_WARNING_RATIO: f64 = 0.001;
+/// Mean sample value in a window that counts as DC offset.
+const DC_WARNING_LEVEL: f64 = 0.05;
+/// Lowest level reported, instead of minus infinity for digital silence.
+const FLOOR_DBFS: f32 = -100.0;
+
+/// One throttled reading for the level meter.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LevelReading {
+    pub rms_dbfs: f32,
+    pub peak_dbfs: f32,
+    pub clipped: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelWarning {
+    /// Nothing above `SILENCE_DBFS` for `SILENCE_WARNING_MS`: muted or wrong
+    /// microphone.
+    Silence,
+    Clipping,
+    DcOffset,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LevelEvent {
+    Reading(LevelReading),
+    Warning(LevelWarning),
+}
+
+/// Level statistics for a whole session.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelStats {
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    pub clipped_samples: u64,
+    pub longest_silence_ms: u32,
+    /// Each warning raised during the session, once.
+    pub warnings: Vec<LevelWarning>,
+}
+
+impl Default for LevelStats {
+    fn default() -> Self {
+        Self {
+            peak_dbfs: FLOOR_DBFS,
+            rms_dbfs: FLOOR_DBFS,
+            clipped_samples: 0,
+            longest_silence_ms: 0,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Measures RMS and peak levels of mono float audio, reports them every
+/// `LEVEL_INTERVAL_MS` and warns about sustained silence, clipping and DC
+/// offset. Each warning fires once per episode and again only after the
+/// condition has cleared.
+pub struct LevelMeter {
+    interval_len: usize,
+    interval_count: usize,
+    interval_energy: f64,
+    interval_peak: f32,
+    interval_clipped: bool,
+    window_len: usize,
+    window_count: usize,
+    window_sum: f64,
+    window_clipped: usize,
+    silence_ms: u32,
+    silence_warned: bool,
+    clipping: bool,
+    dc_offset: bool,
+    total_energy: f64,
+    total_count: u64,
+    peak: f32,
+    clipped_samples: u64,
+    longest_silence_ms: u32,
+    warnings: Vec<LevelWarning>,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let samples_for =
+            |ms: u32| ((u64::from(sample_rate) * u64::from(ms) / 1000) as usize).max(1);
+        Self {
+            interval_len: samples_for(LEVEL_INTERVAL_MS),
+            interval_count: 0,
+            interval_energy: 0.0,
+            interval_peak: 0.0,
+            interval_clipped: false,
+            window_len: samples_for(ANALYSIS_WINDOW_MS),
+            window_count: 0,
+            window_sum: 0.0,
+            window_clipped: 0,
+            silence_ms: 0,
+            silence_warned: false,
+            clipping: false,
+            dc_offset: false,
+            total_energy: 0.0,
+            total_count: 0,
+            peak: 0.0,
+            clipped_samples: 0,
+            longest_silence_ms: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32], mut emit: impl FnMut(LevelEvent)) {
+        for &sample in samples {
+            let magnitude = sample.abs();
+            let energy = f64::from(sample) * f64::from(sample);
+            let clipped = magnitude >= CLIP_LEVEL;
+
+            self.interval_energy += energy;
+            self.interval_peak = self.interval_peak.max(magnitude);
+            self.interval_clipped |= clipped;
+            self.interval_count += 1;
+            self.window_sum += f64::from(sample);
+            self.window_clipped += usize::from(clipped);
+            self.window_count += 1;
+            self.total_energy += energy;
+            self.total_count += 1;
+            self.peak = self.peak.max(magnitude);
+            self.clipped_samples += u64::from(clipped);
+
+            if self.interval_count == self.interval_len {
+                self.finish_interval(&mut emit);
+            }
+            if self.window_count == self.window_len {
+                self.finish_window(&mut emit);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> LevelStats {
+        let mean_energy = if self.total_count == 0 {
+            0.0
+        } else {
+            self.total_energy / self.total_count as f64
+        };
+        LevelStats {
+            peak_dbfs: to_dbfs(self.peak),
+            rms_dbfs: to_dbfs(mean_energy.sqrt() as f32),
+            clipped_samples: self.clipped_samples,
+            longest_silence_ms: self.longest_silence_ms,
+            warnings: self.warnings.clone(),
+        }
+    }
+
+    fn finish_interval(&mut self, emit: &mut impl FnMut(LevelEvent)) {
+        let rms = (self.interval_energy / self.interval_count as f64).sqrt() as f32;
+        let reading = LevelReading {
+            rms_dbfs: to_dbfs(rms),
+            peak_dbfs: to_dbfs(self.interval_peak),
+            clipped: self.interval_clipped,
+        };
+        self.interval_count = 0;
+        self.interval_energy = 0.0;
+        self.interval_peak = 0.0;
+        self.interval_clipped = false;
+        emit(LevelEvent::Reading(reading));
+
+        if reading.rms_dbfs < SILENCE_DBFS {
+            self.silence_ms += LEVEL_INTERVAL_MS;
+            self.longest_silence_ms = self.longest_silence_ms.max(self.silence_ms);
+            if self.silence_ms >= SILENCE_WARNING_MS && !self.silence_warned {
+                self.silence_warned = true;
+                self.warn(LevelWarning::Silence, emit);
+            }
+        } else {
+            self.silence_ms = 0;
+            self.silence_warned = false;
+        }
+    }
+
+    fn finish_window(&mut self, emit: &mut impl FnMut(LevelEvent)) {
+        let count = self.window_count as f64;
+        let clipping = self.window_clipped as f64 / count > CLIP_WARNING_RATIO;
+        let dc_offset = (self.window_sum / count).abs() > DC_WARNING_LEVEL;
+        self.window_count = 0;
+        self.window_sum = 0.0;
+        self.window_clipped = 0;
+
+        if clipping && !self.clipping {
+            self.warn(LevelWarning::Clipping, emit);
+        }
+        if dc_offset && !self.dc_offset {
+            self.warn(LevelWarning::DcOffset, emit);
+        }
+        self.clipping = clipping;
+        self.dc_offset = dc_offset;
+    }
+
+    fn warn(&mut self, warning: LevelWarning, emit: &mut impl FnMut(LevelEvent)) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+        emit(LevelEvent::Warning(warning));
+    }
+}
+
+fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DBFS;
+    }
+    (20.0 * amplitude.log10()).max(FLOOR_DBFS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    fn sine(amplitude: f32, offset: f32, ms: u32) -> Vec<f32> {
+        let len = (RATE * ms / 1000) as usize;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                offset + amplitude * (2.0 * std::f32::consts::PI * 1_000.0 * t).sin()
+            })
+            .collect()
+    }
+
+    fn run(samples: &[f32]) -> (Vec<LevelReading>, Vec<LevelWarning>) {
+        let mut meter = LevelMeter::new(RATE);
+        let mut readings = Vec::new();
+        let mut warnings = Vec::new();
+        meter.process(samples, |event| match event {
+            LevelEvent::Reading(reading) => readings.push(reading),
+            LevelEvent::Warning(warning) => warnings.push(warning),
+        });
+        (readings, warnings)
+    }
+
+    #[test]
+    fn sine_levels_match_theory() {
+        let mut meter = LevelMeter::new(RATE);
+        let mut readings = Vec::new();
+        meter.process(&sine(0.5, 0.0, 1_000), |event| {
+            if let LevelEvent::Reading(reading) = event {
+                readings.push(reading);
+            }
+        });
+
+        assert_eq!(readings.len(), (1_000 / LEVEL_INTERVAL_MS) as usize);
+        let rms = 20.0 * (0.5 / 2f32.sqrt()).log10();
+        let peak = 20.0 * 0.5f32.log10();
+        for reading in &readings {
+            assert!((reading.rms_dbfs - rms).abs() < 0.05, "{reading:?}");
+            assert!((reading.peak_dbfs - peak).abs() < 0.05, "{reading:?}");
+            assert!(!reading.clipped);
+        }
+        let stats = meter.stats();
+        assert!((stats.rms_dbfs - rms).abs() < 0.05);
+        assert!((stats.peak_dbfs - peak).abs() < 0.05);
+        assert!(stats.warnings.is_empty());
+    }
+
+    #[test]
+    fn digital_silence_reports_the_floor() {
+        let (readings, _) = run(&[0.0; RATE as usize / 10]);
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].rms_dbfs, FLOOR_DBFS);
+        assert_eq!(readings[0].peak_dbfs, FLOOR_DBFS);
+    }
+
+    #[test]
+    fn silence_warns_once_after_the_threshold() {
+        let short = vec![0.0; (RATE * (SILENCE_WARNING_MS - LEVEL_INTERVAL_MS) / 1000) as usize];
+        assert!(run(&short).1.is_empty());
+
+        let long = vec![0.0; (RATE * 2 * SILENCE_WARNING_MS / 1000) as usize];
+        assert_eq!(run(&long).1, vec![LevelWarning::Silence]);
+
+        // A quiet room sits above the silence threshold
+        let quiet = sine(0.01, 0.0, 2 * SILENCE_WARNING_MS);
+        assert!(run(&quiet).1.is_empty());
+    }
+
+    #[test]
+    fn silence_warning_rearms_after_sound() {
+        let mut samples = vec![0.0; (RATE * SILENCE_WARNING_MS / 1000) as usize];
+        samples.extend(sine(0.5, 0.0, 500));
+        samples.extend(vec![0.0; (RATE * SILENCE_WARNING_MS / 1000) as usize]);
+        assert_eq!(
+            run(&samples).1,
+            vec![LevelWarning::Silence, LevelWarning::Silence]
+        );
+    }
+
+    #[test]
+    fn clipping_warns_above_the_ratio() {
+        let limit = (RATE as f64 * CLIP_WARNING_RATIO) as usize;
+        let with_clips = |count: usize| {
+            let mut samples = sine(0.1, 0.0, ANALYSIS_WINDOW_MS);
+            for sample in samples.iter_mut().step_by(7).take(count) {
+                *sample = 1.0;
+            }
+            samples
+        };
+
+        assert!(run(&with_clips(limit)).1.is_empty());
+        let (readings, warnings) = run(&with_clips(limit + 1));
+        assert_eq!(warnings, vec![LevelWarning::Clipping]);
+        assert!(readings.iter().any(|r| r.clipped));
+    }
+
+    #[test]
+    fn dc_offset_warns_above_the_level() {
+        let below = sine(0.2, DC_WARNING_LEVEL as f32 - 0.01, ANALYSIS_WINDOW_MS);
+        assert!(run(&below).1.is_empty());
+        let above = sine(0.2, DC_WARNING_LEVEL as f32 + 0.01, ANALYSIS_WINDOW_MS);
+        assert_eq!(run(&above).1, vec![LevelWarning::DcOffset]);
+        let negative = sine(0.2, -(DC_WARNING_LEVEL as f32 + 0.01), ANALYSIS_WINDOW_MS);
+        assert_eq!(run(&negative).1, vec![LevelWarning::DcOffset]);
+    }
+
+    #[test]
+    fn window_warnings_fire_once_per_episode() {
+        let offset = sine(0.2, 0.1, 3 * ANALYSIS_WINDOW_MS);
+        assert_eq!(run(&offset).1, vec![LevelWarning::DcOffset]);
+    }
+}
@@ -9,6 +9,24 @@ pub enum PasteOutcome {
     SimulatedPaste,
 }
 
+impl PasteOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PasteOutcome::ClipboardOnly => "clipboard",
+            PasteOutcome::SimulatedPaste => "pasted",
+        }
+    }
+}
+
+/// Name of the application that currently has keyboard focus, if the
+/// platform exposes it.
+pub fn focused_app_name() -> Option<String> {
+    active_win_pos_rs::get_active_window()
+        .ok()
+        .map(|window| window.app_name)
+        .filter(|name| !name.trim().is_empty())
+}
+
 #[derive(Default)]
 pub struct PasteManager;
 
@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use cpal::FromSample;
+use crossbeam_queue::ArrayQueue;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::sample::{downmix, FullScale};
+
+/// Once this many chunks are waiting, new audio is appended to the last one
+/// so that a backlog drains in fewer, larger messages.
+const MERGE_AFTER_CHUNKS: usize = 4;
+
+/// Counters kept by the sending side for the session summary.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueStats {
+    pub pushed_samples: u64,
+    pub dropped_samples: u64,
+    pub peak_queued_samples: usize,
+}
+
+struct State {
+    chunks: VecDeque<Vec<i16>>,
+    queued: usize,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+/// Bounded PCM16 queue between the capture thread and the backend. It holds at
+/// most `capacity` samples; when full, the oldest audio is dropped so latency
+/// stays bounded, and the loss is counted.
+pub fn audio_queue(capacity: usize, max_chunk: usize) -> (AudioSender, AudioReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            chunks: VecDeque::new(),
+            queued: 0,
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        notify: Notify::new(),
+    });
+    (
+        AudioSender {
+            shared: shared.clone(),
+            capacity: capacity.max(1),
+            max_chunk,
+            stats: QueueStats::default(),
+        },
+        AudioReceiver { shared },
+    )
+}
+
+pub struct AudioSender {
+    shared: Arc<Shared>,
+    capacity: usize,
+    max_chunk: usize,
+    stats: QueueStats,
+}
+
+impl AudioSender {
+    /// Queues a chunk, dropping the oldest queued audio if it does not fit.
+    /// Returns the number of samples dropped, or `None` once the receiver is
+    /// gone.
+    pub fn push(&mut self, mut chunk: Vec<i16>) -> Option<usize> {
+        let mut state = self.shared.state.lock();
+        if state.receiver_closed {
+            return None;
+        }
+        self.stats.pushed_samples += chunk.len() as u64;
+
+        let mut dropped = 0;
+        if chunk.len() > self.capacity {
+            let excess = chunk.len() - self.capacity;
+            chunk.drain(..excess);
+            dropped += excess;
+        }
+        while state.queued + chunk.len() > self.capacity {
+            let excess = state.queued + chunk.len() - self.capacity;
+            let Some(front) = state.chunks.front_mut() else {
+                break;
+            };
+            let removed = if front.len() <= excess {
+                state.chunks.pop_front().map_or(0, |front| front.len())
+            } else {
+                front.drain(..excess);
+                excess
+            };
+            state.queued -= removed;
+            dropped += removed;
+        }
+
+        state.queued += chunk.len();
+        let merge = state.chunks.len() >= MERGE_AFTER_CHUNKS
+            && state
+                .chunks
+                .back()
+                .is_some_and(|back| back.len() + chunk.len() <= self.max_chunk);
+        match state.chunks.back_mut() {
+            Some(back) if merge => back.extend_from_slice(&chunk),
+            _ => state.chunks.push_back(chunk),
+        }
+        self.stats.dropped_samples += dropped as u64;
+        self.stats.peak_queued_samples = self.stats.peak_queued_samples.max(state.queued);
+        drop(state);
+        self.shared.notify.notify_one();
+        Some(dropped)
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.stats
+    }
+}
+
+impl Drop for AudioSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().sender_closed = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+pub struct AudioReceiver {
+    shared: Arc<Shared>,
+}
+
+impl AudioReceiver {
+    /// Waits for the next chunk. Returns `None` once capture has ended and the
+    /// queue is drained. Cancel-safe, so it can be used in `select!`.
+    pub async fn recv(&mut self) -> Option<Vec<i16>> {
+        loop {
+            {
+                let mut state = self.shared.state.lock();
+                if let Some(chunk) = state.chunks.pop_front() {
+                    state.queued -= chunk.len();
+                    return Some(chunk);
+                }
+                if state.sender_closed {
+                    return None;
+                }
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for AudioReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receiver_closed = true;
+        state.chunks.clear();
+        state.queued = 0;
+    }
+}
+
+/// Single-producer, single-consumer sample ring between the audio callback and
+/// the bridge thread. Storage is allocated up front and access is lock-free,
+/// so the real-time callback neither allocates nor blocks.
+pub struct CaptureRing {
+    samples: ArrayQueue<f32>,
+    overflowed: AtomicU64,
+}
+
+impl CaptureRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: ArrayQueue::new(capacity.max(1)),
+            overflowed: AtomicU64::new(0),
+        }
+    }
+
+    /// Producer side, called from the audio callback with interleaved
+    /// `data`, which is downmixed as it is written. Samples that do not fit
+    /// are counted and discarded.
+    pub fn push_frames<T>(&self, data: &[T], channels: usize, channel: Option<usize>)
+    where
+        T: FullScale,
+        f32: FromSample<T>,
+    {
+        let mut overflowed = 0;
+        for value in downmix(data, channels, channel) {
+            if self.samples.push(value).is_err() {
+                overflowed += 1;
+            }
+        }
+        if overflowed > 0 {
+            self.overflowed.fetch_add(overflowed, Ordering::Relaxed);
+        }
+    }
+
+    /// Consumer side: appends everything queued so far to `out`.
+    pub fn drain_into(&self, out: &mut Vec<f32>) {
+        out.reserve(self.samples.len());
+        while let Some(value) = self.samples.pop() {
+            out.push(value);
+        }
+    }
+
+    /// Samples lost because the bridge thread fell behind the callback.
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn drain(rx: &mut AudioReceiver) -> Vec<Vec<i16>> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = block_on(rx.recv()) {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[test]
+    fn overflow_drops_the_oldest_audio() {
+        let (mut tx, mut rx) = audio_queue(10, 100);
+        assert_eq!(tx.push(vec![1, 2, 3, 4]), Some(0));
+        assert_eq!(tx.push(vec![5, 6, 7, 8]), Some(0));
+        assert_eq!(tx.push(vec![9, 10, 11, 12]), Some(2));
+        assert_eq!(tx.push(vec![13, 14, 15, 16, 17]), Some(5));
+
+        let stats = tx.stats();
+        assert_eq!(stats.pushed_samples, 17);
+        assert_eq!(stats.dropped_samples, 7);
+        assert_eq!(stats.peak_queued_samples, 10);
+
+        drop(tx);
+        assert_eq!(
+            drain(&mut rx),
+            vec![vec![8], vec![9, 10, 11, 12], vec![13, 14, 15, 16, 17]]
+        );
+    }
+
+    #[test]
+    fn oversized_chunk_keeps_its_newest_samples() {
+        let (mut tx, mut rx) = audio_queue(4, 100);
+        assert_eq!(tx.push(vec![1, 2]), Some(0));
+        assert_eq!(tx.push(vec![3, 4, 5, 6, 7, 8]), Some(4));
+        assert_eq!(tx.stats().dropped_samples, 4);
+
+        drop(tx);
+        assert_eq!(drain(&mut rx), vec![vec![5, 6, 7, 8]]);
+    }
+
+    #[test]
+    fn backlog_is_merged_up_to_max_chunk() {
+        let (mut tx, mut rx) = audio_queue(1_000, 6);
+        for value in 0..MERGE_AFTER_CHUNKS as i16 {
+            tx.push(vec![value; 2]);
+        }
+        // Appended to the last waiting chunk until it reaches `max_chunk`
+        tx.push(vec![10; 2]);
+        tx.push(vec![11; 2]);
+        tx.push(vec![12; 2]);
+
+        let stats = tx.stats();
+        assert_eq!(stats.pushed_samples, 14);
+        assert_eq!(stats.dropped_samples, 0);
+        assert_eq!(stats.peak_queued_samples, 14);
+
+        drop(tx);
+        let chunks = drain(&mut rx);
+        let lens: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lens, [2, 2, 2, 6, 2]);
+        assert_eq!(chunks[3], [3, 3, 10, 10, 11, 11]);
+        assert_eq!(chunks[4], [12, 12]);
+    }
+
+    #[test]
+    fn short_queue_is_not_merged() {
+        let (mut tx, mut rx) = audio_queue(1_000, 100);
+        for value in 0..MERGE_AFTER_CHUNKS as i16 {
+            tx.push(vec![value]);
+        }
+        drop(tx);
+        assert_eq!(drain(&mut rx).len(), MERGE_AFTER_CHUNKS);
+    }
+
+    #[test]
+    fn receiver_drains_before_reporting_the_end() {
+        let (mut tx, mut rx) = audio_queue(100, 100);
+        tx.push(vec![1, 2]);
+        tx.push(vec![3]);
+        assert_eq!(block_on(rx.recv()), Some(vec![1, 2]));
+        drop(tx);
+        assert_eq!(block_on(rx.recv()), Some(vec![3]));
+        assert_eq!(block_on(rx.recv()), None);
+    }
+
+    #[test]
+    fn push_fails_once_the_receiver_is_gone() {
+        let (mut tx, rx) = audio_queue(100, 100);
+        drop(rx);
+        assert_eq!(tx.push(vec![1, 2, 3]), None);
+        assert_eq!(tx.stats().pushed_samples, 0);
+    }
+}
@@ -1,28 +1,183 @@
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 use bytes::BytesMut;
-use futures::{SinkExt, StreamExt};
+use futures::future::BoxFuture;
+use futures::{FutureExt, SinkExt, StreamExt};
 use serde_json::Value;
-use tauri::http::{HeaderValue, Request};
-use tokio::sync::mpsc;
+use tauri::http::{HeaderName, HeaderValue, Request};
 use tokio::time::{sleep, Duration};
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{client::IntoClientRequest, protocol::Message},
 };
 use tracing::{debug, info, warn};
+use url::Url;
 
 use crate::errors::{AppError, AppResult};
+use crate::queue::AudioReceiver;
+use crate::settings::{AuthScheme, EndpointProfile, VadSettings};
+use crate::transcription::{
+    SessionContext, TranscriptSink, TranscriptUpdate, TranscriptionBackend,
+};
+use crate::vad::VadEvent;
 
 const MAX_CONNECT_ATTEMPTS: usize = 4;
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The realtime API takes 24 kHz mono PCM16 (`pcm16` input format).
+pub const REALTIME_SAMPLE_RATE: u32 = 24_000;
+const TRANSCRIBE_INSTRUCTIONS: &str = "Transcribe the latest audio sample";
+
+/// Streams audio to the OpenAI Realtime API over a WebSocket.
+pub struct RealtimeBackend {
+    api_key: String,
+    model: String,
+    endpoint: EndpointProfile,
+}
+
+impl RealtimeBackend {
+    pub fn new(api_key: String, model: String, endpoint: EndpointProfile) -> Self {
+        Self {
+            api_key,
+            model,
+            endpoint,
+        }
+    }
+}
+
+impl TranscriptionBackend for RealtimeBackend {
+    fn name(&self) -> &'static str {
+        "openai-realtime"
+    }
+
+    fn sample_rate(&self) -> u32 {
+        REALTIME_SAMPLE_RATE
+    }
+
+    fn transcribe(
+        self: Box<Self>,
+        audio_rx: AudioReceiver,
+        ctx: SessionContext,
+    ) -> BoxFuture<'static, AppResult<String>> {
+        stream_transcription(self.api_key, self.model, self.endpoint, audio_rx, ctx).boxed()
+    }
+}
+
+struct TranscriptAssembler {
+    session_id: u64,
+    sequence: u64,
+    text: String,
+    sink: TranscriptSink,
+    /// Output item the last delta belonged to.
+    item_id: Option<String>,
+    /// Set when a response completed; with server turn detection each turn
+    /// gets its own response, so the next delta starts a new turn.
+    turn_ended: bool,
+}
+
+impl TranscriptAssembler {
+    fn new(session_id: u64, sink: TranscriptSink) -> Self {
+        Self {
+            session_id,
+            sequence: 0,
+            text: String::new(),
+            sink,
+            item_id: None,
+            turn_ended: false,
+        }
+    }
+
+    /// Appends a delta, separating it from the previous turn with a space so
+    /// turns do not run into each other. The separator is part of the delta
+    /// sent to the UI.
+    fn push_delta(&mut self, item_id: Option<&str>, delta: &str) {
+        if delta.is_empty() {
+            return;
+        }
+        let new_item = item_id.is_some_and(|id| self.item_id.as_deref() != Some(id));
+        let new_turn = std::mem::take(&mut self.turn_ended) || new_item;
+        if new_item {
+            self.item_id = item_id.map(str::to_string);
+        }
+        let mut delta = delta.to_string();
+        if new_turn
+            && !self.text.is_empty()
+            && !self.text.ends_with(char::is_whitespace)
+            && !delta.starts_with(char::is_whitespace)
+        {
+            delta.insert(0, ' ');
+        }
+        self.text.push_str(&delta);
+        self.sequence += 1;
+        (self.sink)(TranscriptUpdate {
+            session_id: self.session_id,
+            sequence: self.sequence,
+            delta,
+            text: None,
+            is_final: false,
+        });
+    }
+
+    fn end_turn(&mut self) {
+        self.turn_ended = true;
+    }
+
+    /// Sends the final update with the whole text, also after a failure so
+    /// the UI does not keep showing interim text.
+    fn finish(mut self) -> String {
+        self.sequence += 1;
+        (self.sink)(TranscriptUpdate {
+            session_id: self.session_id,
+            sequence: self.sequence,
+            delta: String::new(),
+            text: Some(self.text.clone()),
+            is_final: true,
+        });
+        self.text
+    }
+}
 
 pub async fn stream_transcription(
     api_key: String,
     model: String,
-    sample_rate: u32,
-    mut audio_rx: mpsc::Receiver<Vec<i16>>,
+    endpoint: EndpointProfile,
+    audio_rx: AudioReceiver,
+    ctx: SessionContext,
 ) -> AppResult<String> {
-    let request = build_request(&api_key, &model)?;
+    let mut transcript = TranscriptAssembler::new(ctx.session_id, ctx.sink.clone());
+    let result = run_session(api_key, model, endpoint, audio_rx, ctx, &mut transcript).await;
+    let transcript = transcript.finish();
+    result?;
+
+    if transcript.trim().is_empty() {
+        return Err(AppError::Realtime(
+            "No transcript received from realtime endpoint".into(),
+        ));
+    }
+    info!(length = transcript.len(), "transcription completed");
+    Ok(transcript)
+}
+
+async fn run_session(
+    api_key: String,
+    model: String,
+    endpoint: EndpointProfile,
+    mut audio_rx: AudioReceiver,
+    ctx: SessionContext,
+    transcript: &mut TranscriptAssembler,
+) -> AppResult<()> {
+    let SessionContext {
+        sample_rate,
+        turn_detection,
+        vad_events,
+        ..
+    } = ctx;
+    let credential = match endpoint.auth_scheme {
+        AuthScheme::EphemeralSubprotocol => {
+            mint_ephemeral_token(&endpoint, &api_key, &model).await?
+        }
+        _ => api_key,
+    };
+    let request = build_request(&endpoint, &credential, &model)?;
     let mut backoff = Duration::from_millis(400);
     let mut attempt = 0usize;
     let ws = loop {
@@ -39,32 +194,133 @@ pub async fn stream_transcription(
     };
 
     let (mut write, mut read) = ws.split();
+    if let Some(vad) = &turn_detection {
+        write
+            .send(Message::Text(session_update(vad).to_string().into()))
+            .await
+            .map_err(|err| AppError::Realtime(err.to_string()))?;
+    }
+    let mut committed = false;
     let mut total_samples: usize = 0;
     let mut chunk_counter = 0usize;
+    let mut capturing = true;
+    // Server turn detection can commit several turns while capture runs, each
+    // answered by its own response; the session ends once capture has stopped
+    // and every committed turn has its response.
+    let mut pending_responses = 0usize;
+    let mut speaking = false;
 
-    while let Some(chunk) = audio_rx.recv().await {
-        if chunk.is_empty() {
-            continue;
+    // Reads and writes are multiplexed so server errors surface while the
+    // microphone is still open instead of after the user stops.
+    loop {
+        tokio::select! {
+            chunk = audio_rx.recv(), if capturing => match chunk {
+                Some(chunk) if chunk.is_empty() => continue,
+                Some(chunk) => {
+                    total_samples += chunk.len();
+                    chunk_counter += 1;
+                    let payload = serde_json::json!({
+                        "type": "input_audio_buffer.append",
+                        "audio": encode_samples(&chunk),
+                    });
+                    write
+                        .send(Message::Text(payload.to_string().into()))
+                        .await
+                        .map_err(|err| AppError::Realtime(err.to_string()))?;
+                    let ms = (total_samples as f32 / sample_rate as f32) * 1000.0;
+                    debug!(
+                        chunk = chunk_counter,
+                        samples = chunk.len(),
+                        approx_ms = ms,
+                        "appended audio chunk"
+                    );
+                }
+                None => {
+                    capturing = false;
+                    // Server turn detection may already have committed the
+                    // audio; only a turn cut off mid-speech still needs one
+                    if !committed {
+                        finish_input(&mut write, total_samples, sample_rate).await?;
+                    } else if speaking {
+                        commit_turn(&mut write).await?;
+                    } else if pending_responses == 0 {
+                        break;
+                    }
+                }
+            },
+            msg = read.next() => {
+                let Some(msg) = msg else {
+                    return Err(AppError::Realtime("connection closed".into()));
+                };
+                match handle_server_message(msg, transcript)? {
+                    ServerFlow::Continue => {}
+                    ServerFlow::SpeechStarted => {
+                        speaking = true;
+                        vad_events(VadEvent::SpeechStarted);
+                    }
+                    ServerFlow::SpeechStopped => {
+                        speaking = false;
+                        vad_events(VadEvent::SpeechEnded);
+                    }
+                    ServerFlow::Committed => {
+                        if capturing && turn_detection.is_some() {
+                            vad_events(VadEvent::EndOfUtterance);
+                        }
+                        committed = true;
+                        pending_responses += 1;
+                    }
+                    ServerFlow::Completed => {
+                        if capturing && !committed {
+                            return Err(AppError::Realtime(
+                                "response completed before audio was committed".into(),
+                            ));
+                        }
+                        pending_responses = pending_responses.saturating_sub(1);
+                        if !capturing && pending_responses == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
         }
-        total_samples += chunk.len();
-        chunk_counter += 1;
-        let payload = serde_json::json!({
-            "type": "input_audio_buffer.append",
-            "audio": encode_samples(&chunk),
-        });
-        write
-            .send(Message::Text(payload.to_string().into()))
-            .await
-            .map_err(|err| AppError::Realtime(err.to_string()))?;
-        let ms = (total_samples as f32 / sample_rate as f32) * 1000.0;
-        debug!(
-            chunk = chunk_counter,
-            samples = chunk.len(),
-            approx_ms = ms,
-            "appended audio chunk"
-        );
     }
+    Ok(())
+}
 
+#[derive(Debug, PartialEq, Eq)]
+enum ServerFlow {
+    Continue,
+    SpeechStarted,
+    SpeechStopped,
+    /// The input buffer was committed, by us or by server turn detection.
+    Committed,
+    Completed,
+}
+
+/// Enables server-side turn detection: the server commits the buffer and
+/// starts the transcription response once trailing silence is detected.
+fn session_update(vad: &VadSettings) -> Value {
+    serde_json::json!({
+        "type": "session.update",
+        "session": {
+            "modalities": ["text"],
+            "instructions": TRANSCRIBE_INSTRUCTIONS,
+            "turn_detection": {
+                "type": "server_vad",
+                "threshold": vad.server_threshold,
+                "prefix_padding_ms": vad.prefix_padding_ms,
+                "silence_duration_ms": vad.trailing_silence_ms,
+                "create_response": true
+            }
+        }
+    })
+}
+
+async fn finish_input<S>(write: &mut S, total_samples: usize, sample_rate: u32) -> AppResult<()>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
     if total_samples == 0 {
         return Err(AppError::AudioEmpty);
     }
@@ -76,6 +332,15 @@ pub async fn stream_transcription(
         )));
     }
 
+    commit_turn(write).await
+}
+
+/// Commits the buffered audio and requests its transcription.
+async fn commit_turn<S>(write: &mut S) -> AppResult<()>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
     write
         .send(Message::Text(
             serde_json::json!({"type": "input_audio_buffer.commit"})
@@ -90,7 +355,7 @@ pub async fn stream_transcription(
                 "type": "response.create",
                 "response": {
                     "modalities": ["text"],
-                    "instructions": "Transcribe the latest audio sample"
+                    "instructions": TRANSCRIBE_INSTRUCTIONS
                 }
             })
             .to_string()
@@ -98,74 +363,130 @@ pub async fn stream_transcription(
         ))
         .await
         .map_err(|err| AppError::Realtime(err.to_string()))?;
+    Ok(())
+}
 
-    let mut transcript = String::new();
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(body)) => {
-                let value: Value = serde_json::from_str(&body)
-                    .map_err(|err| AppError::Realtime(err.to_string()))?;
-                if let Some(event_type) = value.get("type").and_then(|v| v.as_str()) {
-                    match event_type {
-                        "response.output_text.delta" => {
-                            if let Some(delta) = value.get("delta").and_then(|v| v.as_str()) {
-                                transcript.push_str(delta);
-                            }
-                        }
-                        "response.completed" => break,
-                        "error" => {
-                            let message = value
-                                .get("error")
-                                .and_then(|v| v.get("message"))
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("unknown error");
-                            return Err(AppError::Realtime(message.to_string()));
-                        }
-                        _ => {}
+fn handle_server_message(
+    msg: Result<Message, tokio_tungstenite::tungstenite::Error>,
+    transcript: &mut TranscriptAssembler,
+) -> AppResult<ServerFlow> {
+    match msg {
+        Ok(Message::Text(body)) => {
+            let value: Value =
+                serde_json::from_str(&body).map_err(|err| AppError::Realtime(err.to_string()))?;
+            let Some(event_type) = value.get("type").and_then(|v| v.as_str()) else {
+                return Ok(ServerFlow::Continue);
+            };
+            match event_type {
+                "response.output_text.delta" | "response.text.delta" => {
+                    if let Some(delta) = value.get("delta").and_then(|v| v.as_str()) {
+                        let item_id = value.get("item_id").and_then(|v| v.as_str());
+                        transcript.push_delta(item_id, delta);
                     }
                 }
+                "response.completed" | "response.done" => {
+                    transcript.end_turn();
+                    return Ok(ServerFlow::Completed);
+                }
+                "input_audio_buffer.speech_started" => return Ok(ServerFlow::SpeechStarted),
+                "input_audio_buffer.speech_stopped" => return Ok(ServerFlow::SpeechStopped),
+                "input_audio_buffer.committed" => return Ok(ServerFlow::Committed),
+                "error" => {
+                    let message = value
+                        .get("error")
+                        .and_then(|v| v.get("message"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("unknown error");
+                    return Err(AppError::Realtime(message.to_string()));
+                }
+                other => debug!(event = other, "realtime server event"),
             }
-            Ok(Message::Close(frame)) => {
-                let reason = frame
-                    .map(|f| f.reason.to_string())
-                    .unwrap_or_else(|| "connection closed".into());
-                return Err(AppError::Realtime(reason));
-            }
-            Ok(_) => continue,
-            Err(err) => return Err(AppError::Realtime(err.to_string())),
+            Ok(ServerFlow::Continue)
         }
+        Ok(Message::Close(frame)) => {
+            let reason = frame
+                .map(|f| f.reason.to_string())
+                .unwrap_or_else(|| "connection closed".into());
+            Err(AppError::Realtime(reason))
+        }
+        Ok(_) => Ok(ServerFlow::Continue),
+        Err(err) => Err(AppError::Realtime(err.to_string())),
     }
-
-    if transcript.trim().is_empty() {
-        return Err(AppError::Realtime(
-            "No transcript received from realtime endpoint".into(),
-        ));
-    }
-
-    info!(length = transcript.len(), "transcription completed");
-    Ok(transcript)
 }
 
-fn build_request(api_key: &str, model: &str) -> AppResult<Request<()>> {
-    let url = format!("wss://api.openai.com/v1/realtime?model={model}");
+/// `credential` is the API key for header-based schemes and an ephemeral
+/// token for `EphemeralSubprotocol`.
+fn build_request(
+    endpoint: &EndpointProfile,
+    credential: &str,
+    model: &str,
+) -> AppResult<Request<()>> {
+    let url = build_url(endpoint, model)?;
     let mut request = url
+        .as_str()
         .into_client_request()
         .map_err(|err| AppError::Realtime(err.to_string()))?;
     let headers = request.headers_mut();
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("Bearer {api_key}"))
-            .map_err(|err| AppError::Realtime(err.to_string()))?,
-    );
+    match endpoint.auth_scheme {
+        AuthScheme::Bearer => {
+            headers.insert(
+                "Authorization",
+                HeaderValue::from_str(&format!("Bearer {credential}"))
+                    .map_err(|err| AppError::Realtime(err.to_string()))?,
+            );
+        }
+        AuthScheme::ApiKeyHeader => {
+            headers.insert(
+                "api-key",
+                HeaderValue::from_str(credential)
+                    .map_err(|err| AppError::Realtime(err.to_string()))?,
+            );
+        }
+        AuthScheme::EphemeralSubprotocol => {
+            let subprotocols = build_subprotocol_header(credential);
+            headers.insert(
+                "Sec-WebSocket-Protocol",
+                HeaderValue::from_str(&subprotocols)
+                    .map_err(|err| AppError::Realtime(err.to_string()))?,
+            );
+        }
+        AuthScheme::None => {}
+    }
     headers.insert("OpenAI-Beta", HeaderValue::from_static("realtime=v1"));
-    let subprotocols = build_subprotocol_header(api_key);
-    headers.insert(
-        "Sec-WebSocket-Protocol",
-        HeaderValue::from_str(&subprotocols).map_err(|err| AppError::Realtime(err.to_string()))?,
-    );
+    for (name, value) in &endpoint.headers {
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|err| AppError::Validation(format!("invalid header name {name:?}: {err}")))?;
+        let value = HeaderValue::from_str(value).map_err(|err| {
+            AppError::Validation(format!("invalid value for header {name}: {err}"))
+        })?;
+        headers.insert(name, value);
+    }
     Ok(request)
 }
 
+fn build_url(endpoint: &EndpointProfile, model: &str) -> AppResult<Url> {
+    let mut url = Url::parse(&endpoint.base_url).map_err(|err| {
+        AppError::Validation(format!(
+            "invalid realtime URL {:?}: {err}",
+            endpoint.base_url
+        ))
+    })?;
+    if !matches!(url.scheme(), "ws" | "wss") {
+        return Err(AppError::Validation(format!(
+            "realtime URL must use ws:// or wss://, got {}://",
+            url.scheme()
+        )));
+    }
+    {
+        let mut pairs = url.query_pairs_mut();
+        pairs.append_pair(&endpoint.model_param, model);
+        for (key, value) in &endpoint.query {
+            pairs.append_pair(key, value);
+        }
+    }
+    Ok(url)
+}
+
 fn encode_samples(samples: &[i16]) -> String {
     let mut buf = BytesMut::with_capacity(samples.len() * 2);
     for sample in samples {
@@ -174,11 +495,72 @@ fn encode_samples(samples: &[i16]) -> String {
     BASE64.encode(&buf)
 }
 
-fn build_subprotocol_header(api_key: &str) -> String {
-    // keep "realtime" first; include insecure api key token for compatibility with hosted envs
+async fn mint_ephemeral_token(
+    endpoint: &EndpointProfile,
+    api_key: &str,
+    model: &str,
+) -> AppResult<String> {
+    let url = token_url(endpoint)?;
+    let response = reqwest::Client::new()
+        .post(url.as_str())
+        .bearer_auth(api_key)
+        .header("OpenAI-Beta", "realtime=v1")
+        .json(&serde_json::json!({ "model": model }))
+        .timeout(TOKEN_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|err| AppError::Realtime(format!("ephemeral token request failed: {err}")))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(AppError::Realtime(format!(
+            "ephemeral token request rejected ({status})"
+        )));
+    }
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|err| AppError::Realtime(err.to_string()))?;
+    body.get("client_secret")
+        .and_then(|secret| secret.get("value"))
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| AppError::Realtime("ephemeral token missing from response".into()))
+}
+
+fn token_url(endpoint: &EndpointProfile) -> AppResult<Url> {
+    if let Some(explicit) = endpoint
+        .token_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+    {
+        return Url::parse(explicit)
+            .map_err(|err| AppError::Validation(format!("invalid token URL {explicit:?}: {err}")));
+    }
+    let mut url = Url::parse(&endpoint.base_url).map_err(|err| {
+        AppError::Validation(format!(
+            "invalid realtime URL {:?}: {err}",
+            endpoint.base_url
+        ))
+    })?;
+    let scheme = if url.scheme() == "ws" {
+        "http"
+    } else {
+        "https"
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| AppError::Validation("unable to derive token URL".into()))?;
+    let path = format!("{}/sessions", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    url.set_query(None);
+    Ok(url)
+}
+
+fn build_subprotocol_header(token: &str) -> String {
+    // keep "realtime" first; only ever called with a short-lived ephemeral token
     let mut entries = Vec::with_capacity(3);
     entries.push("realtime".to_string());
-    entries.push(format!("openai-insecure-api-key.{api_key}"));
+    entries.push(format!("openai-insecure-api-key.{token}"));
     entries.push("openai-beta.realtime-v1".to_string());
     entries.join(",")
 }
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::history::HistoryStore;
+use crate::settings::SettingsStore;
+
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Applies the retention policy at startup and then every hour. Settings are
+/// re-read on each run, so policy changes take effect without a restart;
+/// `save_settings` also applies them immediately.
+pub fn spawn(history: Arc<HistoryStore>, settings: Arc<SettingsStore>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let policy = settings.get().await.retention;
+            if policy.is_unlimited() {
+                continue;
+            }
+            if let Err(err) = history.apply_retention(&policy).await {
+                warn!(error = %err, "history retention failed");
+            }
+        }
+    });
+}
@@ -0,0 +1,164 @@
+use cpal::{FromSample, Sample, I24};
+
+/// A capture sample format and its largest value. Integer formats have one
+/// more negative value than positive ones, so cpal maps their maximum just
+/// below 1.0 (127/128 for `i8`); `downmix` rescales positive samples by it.
+pub trait FullScale: Sample {
+    fn max_value() -> Self;
+}
+
+macro_rules! full_scale {
+    ($($format:ty => $max:expr),* $(,)?) => {
+        $(impl FullScale for $format {
+            fn max_value() -> Self {
+                $max
+            }
+        })*
+    };
+}
+
+full_scale!(
+    i8 => i8::MAX,
+    i16 => i16::MAX,
+    I24 => I24::new_unchecked((1 << 23) - 1),
+    i32 => i32::MAX,
+    i64 => i64::MAX,
+    u8 => u8::MAX,
+    u16 => u16::MAX,
+    u32 => u32::MAX,
+    u64 => u64::MAX,
+    f32 => 1.0,
+    f64 => 1.0,
+);
+
+/// Mixes interleaved frames down to mono floats in `[-1, 1]`, either by taking
+/// one channel or by averaging all of them. Every format's minimum maps to
+/// -1.0 and its maximum to 1.0. Lazy, so the audio callback can write straight
+/// into its ring without allocating.
+pub fn downmix<T>(
+    data: &[T],
+    channels: usize,
+    channel: Option<usize>,
+) -> impl Iterator<Item = f32> + '_
+where
+    T: FullScale,
+    f32: FromSample<T>,
+{
+    let channels = channels.max(1);
+    let positive_scale = 1.0 / T::max_value().to_sample::<f32>();
+    let to_float = move |sample: &T| {
+        let value = sample.to_sample::<f32>();
+        if value > 0.0 {
+            value * positive_scale
+        } else {
+            value
+        }
+    };
+    data.chunks_exact(channels).map(move |frame| match channel {
+        Some(index) if index < channels => to_float(&frame[index]),
+        _ => frame.iter().map(to_float).sum::<f32>() / channels as f32,
+    })
+}
+
+/// Converts a float sample to PCM16, clamping out-of-range input. Negative
+/// values are scaled by 2^15 and positive ones by 2^15 - 1, mirroring
+/// `downmix`, so `i16` input round-trips exactly and -1.0 and 1.0 map to
+/// `i16::MIN` and `i16::MAX`.
+pub fn to_pcm16(value: f32) -> i16 {
+    let scale = if value < 0.0 { 32_768.0 } else { 32_767.0 };
+    (value * scale)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PCM16 for one mono sample, along the capture path of `CaptureRing::push_frames`.
+    fn pcm16<T>(sample: T) -> i16
+    where
+        T: FullScale,
+        f32: FromSample<T>,
+    {
+        downmix(&[sample], 1, None).map(to_pcm16).sum()
+    }
+
+    fn min_mid_max<T>(min: T, mid: T, max: T) -> [i16; 3]
+    where
+        T: FullScale,
+        f32: FromSample<T>,
+    {
+        [pcm16(min), pcm16(mid), pcm16(max)]
+    }
+
+    #[test]
+    fn every_sample_format_spans_pcm16() {
+        let cases = [
+            ("i8", min_mid_max(i8::MIN, 0, i8::MAX)),
+            ("i16", min_mid_max(i16::MIN, 0, i16::MAX)),
+            (
+                "I24",
+                min_mid_max(
+                    I24::new_unchecked(-(1 << 23)),
+                    I24::EQUILIBRIUM,
+                    I24::max_value(),
+                ),
+            ),
+            ("i32", min_mid_max(i32::MIN, 0, i32::MAX)),
+            ("i64", min_mid_max(i64::MIN, 0, i64::MAX)),
+            ("u8", min_mid_max(u8::MIN, 1 << 7, u8::MAX)),
+            ("u16", min_mid_max(u16::MIN, 1 << 15, u16::MAX)),
+            ("u32", min_mid_max(u32::MIN, 1 << 31, u32::MAX)),
+            ("u64", min_mid_max(u64::MIN, 1 << 63, u64::MAX)),
+            ("f32", min_mid_max(-1.0f32, 0.0, 1.0)),
+            ("f64", min_mid_max(-1.0f64, 0.0, 1.0)),
+        ];
+        for (format, pcm) in cases {
+            assert_eq!(pcm, [i16::MIN, 0, i16::MAX], "{format}");
+        }
+    }
+
+    #[test]
+    fn i16_round_trips_exactly() {
+        for sample in (i16::MIN..=i16::MAX).step_by(7).chain([-1, 1, i16::MAX]) {
+            assert_eq!(pcm16(sample), sample);
+        }
+    }
+
+    #[test]
+    fn to_pcm16_clamps_out_of_range_input() {
+        assert_eq!(to_pcm16(1.5), i16::MAX);
+        assert_eq!(to_pcm16(-1.5), i16::MIN);
+    }
+
+    #[test]
+    fn downmix_selects_a_channel() {
+        let frames: [i16; 6] = [100, -200, 300, -400, 500, -600];
+        let left: Vec<i16> = downmix(&frames, 2, Some(0)).map(to_pcm16).collect();
+        let right: Vec<i16> = downmix(&frames, 2, Some(1)).map(to_pcm16).collect();
+        assert_eq!(left, [100, 300, 500]);
+        assert_eq!(right, [-200, -400, -600]);
+    }
+
+    #[test]
+    fn downmix_averages_all_channels() {
+        let frames = [0.2f32, 0.4, 0.6, -0.6, 0.0, 0.3];
+        let mixed: Vec<f32> = downmix(&frames, 3, None).collect();
+        assert_eq!(mixed.len(), 2);
+        assert!((mixed[0] - 0.4).abs() < 1e-6);
+        assert!((mixed[1] - -0.1).abs() < 1e-6);
+        // A channel the device does not have falls back to the average
+        assert_eq!(downmix(&frames, 3, Some(3)).collect::<Vec<_>>(), mixed);
+    }
+
+    #[test]
+    fn downmix_of_right_only_stereo() {
+        // Interfaces that wire a mono mic to the right input only
+        let frames = [0.0f32, 0.8, 0.0, -0.5];
+        let right: Vec<f32> = downmix(&frames, 2, Some(1)).collect();
+        let mixed: Vec<f32> = downmix(&frames, 2, None).collect();
+        assert_eq!(right, [0.8, -0.5]);
+        assert_eq!(mixed, [0.4, -0.25]);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use tracing::{info, warn};
+
+use crate::errors::{AppError, AppResult};
+use crate::fsutil::write_atomic_with_mode;
+
+const KEYRING_SERVICE: &str = "com.coolchatty.CoolChatty";
+const FALLBACK_FILE: &str = "secrets.bin";
+const FALLBACK_KEY_FILE: &str = "secrets.key";
+/// Records which backend holds the secrets of this install.
+const BACKEND_FILE: &str = "secrets.backend";
+const KEYRING_LABEL: &str = "keyring";
+const ENCRYPTED_FILE_LABEL: &str = "encrypted-file";
+const NONCE_LEN: usize = 12;
+
+pub const API_KEY_SECRET: &str = "openai-api-key";
+pub const HISTORY_KEY_SECRET: &str = "history-key";
+/// Holds the new history key while a rotation is in progress.
+pub const HISTORY_KEY_NEXT_SECRET: &str = "history-key-next";
+
+#[derive(Clone, Debug)]
+enum SecretBackend {
+    /// Keychain on macOS, Credential Manager on Windows, Secret Service on Linux.
+    Keyring,
+    /// ChaCha20-Poly1305 encrypted file next to settings.json. The key lives in a
+    /// separate owner-only file, which keeps secrets out of settings backups and
+    /// sync but does not protect against other processes of the same user.
+    EncryptedFile { data: PathBuf, key: PathBuf },
+}
+
+/// Stores credentials outside of settings.json, preferring the OS credential
+/// store and falling back to an encrypted file when none is available.
+#[derive(Clone, Debug)]
+pub struct SecretStore {
+    backend: SecretBackend,
+}
+
+impl SecretStore {
+    /// Uses the backend recorded in the config dir, so secrets never end up
+    /// split between two stores when the keyring is briefly unreachable. The
+    /// first run chooses one and records it.
+    pub fn new(config_dir: &Path) -> Self {
+        let data = config_dir.join(FALLBACK_FILE);
+        let key = config_dir.join(FALLBACK_KEY_FILE);
+        let marker = config_dir.join(BACKEND_FILE);
+        let backend = match fs::read_to_string(&marker).as_deref().map(str::trim) {
+            Ok(KEYRING_LABEL) => {
+                if !keyring_available() {
+                    warn!("OS credential store unavailable, secrets stored there cannot be read");
+                }
+                SecretBackend::Keyring
+            }
+            Ok(ENCRYPTED_FILE_LABEL) => SecretBackend::EncryptedFile { data, key },
+            recorded => {
+                if let Ok(label) = recorded {
+                    warn!(label, "unknown secret backend recorded, choosing again");
+                }
+                let backend = choose_backend(data, key);
+                let recorded = fs::create_dir_all(config_dir)
+                    .map_err(|err| AppError::Secrets(err.to_string()))
+                    .and_then(|()| write_private(&marker, backend_label(&backend).as_bytes()));
+                if let Err(err) = recorded {
+                    warn!(error = %err, "unable to record the secret backend");
+                }
+                backend
+            }
+        };
+        info!(backend = backend_label(&backend), "secret storage ready");
+        Self { backend }
+    }
+
+    pub fn get(&self, name: &str) -> AppResult<Option<String>> {
+        match &self.backend {
+            SecretBackend::Keyring => match entry(name)?.get_password() {
+                Ok(value) => Ok(Some(value)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(err) => Err(AppError::Secrets(err.to_string())),
+            },
+            SecretBackend::EncryptedFile { data, key } => {
+                Ok(read_fallback(data, key)?.remove(name))
+            }
+        }
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> AppResult<()> {
+        match &self.backend {
+            SecretBackend::Keyring => entry(name)?
+                .set_password(value)
+                .map_err(|err| AppError::Secrets(err.to_string())),
+            SecretBackend::EncryptedFile { data, key } => {
+                let mut secrets = read_fallback(data, key)?;
+                secrets.insert(name.to_string(), value.to_string());
+                write_fallback(data, key, &secrets)
+            }
+        }
+    }
+
+    pub fn delete(&self, name: &str) -> AppResult<()> {
+        match &self.backend {
+            SecretBackend::Keyring => match entry(name)?.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(err) => Err(AppError::Secrets(err.to_string())),
+            },
+            SecretBackend::EncryptedFile { data, key } => {
+                let mut secrets = read_fallback(data, key)?;
+                if secrets.remove(name).is_some() {
+                    write_fallback(data, key, &secrets)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Masks a secret for display, keeping a short prefix and the last four
+/// characters so users can tell keys apart.
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.is_empty() {
+        return String::new();
+    }
+    if chars.len() <= 8 {
+        return "••••".into();
+    }
+    let prefix: String = chars[..3].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{prefix}…{suffix}")
+}
+
+fn backend_label(backend: &SecretBackend) -> &'static str {
+    match backend {
+        SecretBackend::Keyring => KEYRING_LABEL,
+        SecretBackend::EncryptedFile { .. } => ENCRYPTED_FILE_LABEL,
+    }
+}
+
+fn entry(name: &str) -> AppResult<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, name).map_err(|err| AppError::Secrets(err.to_string()))
+}
+
+/// Picks the backend for an install without a recorded one, preferring the
+/// keyring. Secrets from an existing fallback file are moved into it.
+fn choose_backend(data: PathBuf, key: PathBuf) -> SecretBackend {
+    if !keyring_available() {
+        warn!("OS credential store unavailable, using encrypted file fallback");
+        return SecretBackend::EncryptedFile { data, key };
+    }
+    if data.exists() {
+        match move_to_keyring(&data, &key) {
+            Ok(moved) => info!(moved, "moved fallback secrets to the keyring"),
+            Err(err) => {
+                warn!(error = %err, "unable to move fallback secrets to the keyring");
+                return SecretBackend::EncryptedFile { data, key };
+            }
+        }
+    }
+    SecretBackend::Keyring
+}
+
+/// Copies fallback secrets the keyring does not hold yet, then removes the
+/// fallback files. Values already in the keyring win.
+fn move_to_keyring(data: &Path, key: &Path) -> AppResult<usize> {
+    let keyring = SecretStore {
+        backend: SecretBackend::Keyring,
+    };
+    let mut moved = 0;
+    for (name, value) in read_fallback(data, key)? {
+        if keyring.get(&name)?.is_none() {
+            keyring.set(&name, &value)?;
+            moved += 1;
+        }
+    }
+    fs::remove_file(data).map_err(|err| AppError::Secrets(err.to_string()))?;
+    let _ = fs::remove_file(key);
+    Ok(moved)
+}
+
+fn keyring_available() -> bool {
+    let probe = entry(API_KEY_SECRET).and_then(|entry| match entry.get_password() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(AppError::Secrets(err.to_string())),
+    });
+    match probe {
+        Ok(()) => true,
+        Err(err) => {
+            warn!(error = %err, "keyring probe failed");
+            false
+        }
+    }
+}
+
+/// Reads the key of an existing secrets file. A missing or malformed key is an
+/// error: a new key could not decrypt the file, and writing with it would
+/// lose every secret stored there.
+fn load_key(path: &Path) -> AppResult<Key> {
+    let bytes = fs::read(path).map_err(|err| {
+        AppError::Secrets(format!(
+            "unable to read secret key file {}: {err}",
+            path.display()
+        ))
+    })?;
+    if bytes.len() != 32 {
+        return Err(AppError::Secrets(format!(
+            "secret key file {} is malformed",
+            path.display()
+        )));
+    }
+    Ok(*Key::from_slice(&bytes))
+}
+
+/// Returns the key to encrypt `data` with, generating one only while no
+/// secrets file exists yet.
+fn load_or_create_key(path: &Path, data: &Path) -> AppResult<Key> {
+    if data.exists() {
+        return load_key(path);
+    }
+    match load_key(path) {
+        Ok(key) => return Ok(key),
+        Err(err) if path.exists() => {
+            warn!(error = %err, "replacing unusable secret key, no secrets depend on it");
+        }
+        Err(_) => {}
+    }
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| AppError::Secrets(err.to_string()))?;
+    }
+    write_private(path, key.as_slice())?;
+    Ok(key)
+}
+
+fn read_fallback(data: &Path, key: &Path) -> AppResult<BTreeMap<String, String>> {
+    let raw = match fs::read(data) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(AppError::Secrets(err.to_string())),
+    };
+    if raw.len() < NONCE_LEN {
+        return Err(AppError::Secrets("secret file is truncated".into()));
+    }
+    let cipher = ChaCha20Poly1305::new(&load_key(key)?);
+    let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| AppError::Secrets("unable to decrypt secret file".into()))?;
+    serde_json::from_slice(&plaintext).map_err(|err| AppError::Secrets(err.to_string()))
+}
+
+fn write_fallback(data: &Path, key: &Path, secrets: &BTreeMap<String, String>) -> AppResult<()> {
+    let cipher = ChaCha20Poly1305::new(&load_or_create_key(key, data)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext =
+        serde_json::to_vec(secrets).map_err(|err| AppError::Secrets(err.to_string()))?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| AppError::Secrets("unable to encrypt secret file".into()))?;
+    let mut body = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    body.extend_from_slice(nonce.as_slice());
+    body.extend_from_slice(&ciphertext);
+    write_private(data, &body)
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> AppResult<()> {
+    write_atomic_with_mode(path, contents, Some(0o600))
+        .map_err(|err| AppError::Secrets(err.to_string()))
+}
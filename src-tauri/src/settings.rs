@@ -1,22 +1,242 @@
-use std::{fs, path::PathBuf};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use directories::ProjectDirs;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tokio::sync::RwLock;
+use tracing::{info, warn};
 
 use crate::errors::{AppError, AppResult};
+use crate::fsutil::write_atomic;
+use crate::secrets::{mask_secret, SecretStore, API_KEY_SECRET};
 
 pub const DEFAULT_REALTIME_MODEL: &str = "gpt-realtime-mini";
+pub const DEFAULT_REALTIME_URL: &str = "wss://api.openai.com/v1/realtime";
+/// Bump together with a new entry in `MIGRATIONS`.
+pub const SETTINGS_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a settings object from version `n` to `n + 1`.
+/// Files written before versioning was introduced count as version 0.
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyMode {
+    /// Each press starts or stops a recording.
+    #[default]
+    Toggle,
+    /// Record only while the hotkey is held down.
+    PushToTalk,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionEngine {
+    /// Stream audio to the OpenAI Realtime API.
+    #[default]
+    Realtime,
+    /// Decode on this machine with a Whisper model; no network access.
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <key>` as used by api.openai.com.
+    #[default]
+    Bearer,
+    /// `api-key: <key>` as used by Azure OpenAI.
+    ApiKeyHeader,
+    /// Browser-style `Sec-WebSocket-Protocol` auth carrying a short-lived
+    /// ephemeral token minted from the real key, never the key itself.
+    EphemeralSubprotocol,
+    /// No credentials; the gateway authenticates by other means.
+    None,
+}
+
+/// Where the realtime client connects and how it authenticates. The defaults
+/// target api.openai.com; Azure OpenAI and internal gateways override them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointProfile {
+    pub base_url: String,
+    pub auth_scheme: AuthScheme,
+    /// Query parameter that carries the model (Azure expects `deployment`).
+    pub model_param: String,
+    pub headers: BTreeMap<String, String>,
+    pub query: BTreeMap<String, String>,
+    /// REST endpoint that mints ephemeral tokens. Derived from `base_url`
+    /// when unset, e.g. `https://api.openai.com/v1/realtime/sessions`.
+    pub token_url: Option<String>,
+}
+
+impl Default for EndpointProfile {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_REALTIME_URL.into(),
+            auth_scheme: AuthScheme::Bearer,
+            model_param: default_model_param(),
+            headers: BTreeMap::new(),
+            query: BTreeMap::new(),
+            token_url: None,
+        }
+    }
+}
+
+fn default_model_param() -> String {
+    "model".into()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VadMode {
+    /// Recording ends only when the user stops it.
+    #[default]
+    Off,
+    /// Energy-based detection on this machine ends the session after trailing
+    /// silence.
+    Local,
+    /// The realtime server detects turns (`turn_detection: server_vad`) and
+    /// commits the audio itself. Falls back to `Local` with the local engine.
+    Server,
+}
+
+/// Voice activity detection and hands-free stop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VadSettings {
+    pub mode: VadMode,
+    /// Silence after speech that ends the utterance.
+    pub trailing_silence_ms: u32,
+    /// Local: how far above the tracked noise floor a frame must be to count
+    /// as speech.
+    pub speech_margin_db: f32,
+    /// Server: activation threshold between 0 and 1.
+    pub server_threshold: f32,
+    /// Server: audio kept before detected speech onset.
+    pub prefix_padding_ms: u32,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            mode: VadMode::Off,
+            trailing_silence_ms: 1_200,
+            speech_margin_db: 10.0,
+            server_threshold: 0.5,
+            prefix_padding_ms: 300,
+        }
+    }
+}
+
+/// Queue between capture and the transcription backend. It absorbs network
+/// stalls; once full, the oldest audio is dropped and counted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioBufferSettings {
+    pub buffer_seconds: u32,
+    /// Share of the captured audio that may be dropped before the session
+    /// fails.
+    pub max_loss_percent: f32,
+}
+
+impl Default for AudioBufferSettings {
+    fn default() -> Self {
+        Self {
+            buffer_seconds: 10,
+            max_loss_percent: 2.0,
+        }
+    }
+}
+
+/// Keeps the microphone open between sessions so that speech starting with
+/// the hotkey press is not cut off. Off by default: the pre-roll only ever
+/// lives in memory, is overwritten continuously and is discarded unless a
+/// session starts, and the mic closes after `idle_timeout_minutes` without a
+/// session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarmMicSettings {
+    pub enabled: bool,
+    /// Audio from before the hotkey press prepended to each session.
+    pub preroll_ms: u32,
+    pub idle_timeout_minutes: u32,
+}
+
+impl Default for WarmMicSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preroll_ms: 500,
+            idle_timeout_minutes: 10,
+        }
+    }
+}
+
+/// Optional clean-up of the captured audio before it is transcribed, applied
+/// in the order listed. All stages are off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingSettings {
+    /// Removes rumble, handling noise and DC below 80 Hz.
+    pub high_pass: bool,
+    /// Attenuates steady background noise such as fans or air conditioning.
+    pub noise_suppression: bool,
+    /// Evens out quiet and loud speakers towards a common level.
+    pub auto_gain: bool,
+}
+
+/// Limits applied to history.db by the background retention task. Unset
+/// limits keep history forever; pinned entries are never purged.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    pub max_age_days: Option<u32>,
+    pub max_entries: Option<u32>,
+    pub max_db_size_mb: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_days.is_none() && self.max_entries.is_none() && self.max_db_size_mb.is_none()
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
+    pub version: u32,
+    /// Held in memory only. The key is persisted in the OS credential store and
+    /// never serialized, neither to settings.json nor to the webview.
+    #[serde(skip_serializing)]
     pub api_key: String,
     pub model: String,
+    pub endpoint: EndpointProfile,
+    pub engine: TranscriptionEngine,
+    pub local_model_path: Option<String>,
+    pub local_language: Option<String>,
     pub hotkey: String,
+    pub hotkey_mode: HotkeyMode,
     pub auto_paste: bool,
     pub save_history: bool,
+    pub retention: RetentionPolicy,
+    /// Preferred device rate; audio is resampled to what the engine expects.
     pub sample_rate: u32,
+    /// Device identifier from `list_input_devices`; unset records from the
+    /// system default. Plain device names saved by older builds still match.
     pub input_device: Option<String>,
+    /// Input channel to record (0-based); unset averages all channels.
+    pub input_channel: Option<u16>,
+    pub vad: VadSettings,
+    pub audio_buffer: AudioBufferSettings,
+    pub warm_mic: WarmMicSettings,
+    pub processing: ProcessingSettings,
 }
 
 impl Default for AppSettings {
@@ -26,64 +246,345 @@ impl Default for AppSettings {
         #[cfg(not(target_os = "macos"))]
         let default_hotkey = "Alt+Space".to_string();
         Self {
+            version: SETTINGS_VERSION,
             api_key: String::new(),
             model: DEFAULT_REALTIME_MODEL.into(),
+            endpoint: EndpointProfile::default(),
+            engine: TranscriptionEngine::Realtime,
+            local_model_path: None,
+            local_language: None,
             hotkey: default_hotkey,
+            hotkey_mode: HotkeyMode::Toggle,
             auto_paste: true,
             save_history: true,
+            retention: RetentionPolicy::default(),
             sample_rate: 16_000,
             input_device: None,
+            input_channel: None,
+            vad: VadSettings::default(),
+            audio_buffer: AudioBufferSettings::default(),
+            warm_mic: WarmMicSettings::default(),
+            processing: ProcessingSettings::default(),
         }
     }
 }
 
+/// What the frontend sees: the settings plus whether a key is stored.
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsView {
+    #[serde(flatten)]
+    pub settings: AppSettings,
+    pub api_key_set: bool,
+    pub api_key_masked: String,
+    /// Set when settings.json could not be loaded and defaults were used.
+    pub load_warning: Option<String>,
+    /// Set while a key from an older settings.json could not be moved to
+    /// secret storage and is still kept in the file.
+    pub api_key_warning: Option<String>,
+}
+
 pub struct SettingsStore {
     path: PathBuf,
+    secrets: SecretStore,
     inner: RwLock<AppSettings>,
+    load_warning: Option<String>,
+    /// Some while the plaintext key in settings.json awaits migration. Every
+    /// persist retries it and keeps the key in the file until it succeeds.
+    api_key_warning: Mutex<Option<String>>,
 }
 
 impl SettingsStore {
     pub fn load() -> AppResult<Self> {
         let path = settings_path()?;
-        let data = if path.exists() {
-            let raw =
-                fs::read_to_string(&path).map_err(|err| AppError::Settings(err.to_string()))?;
-            serde_json::from_str::<AppSettings>(&raw)
-                .map_err(|err| AppError::Settings(err.to_string()))?
-                .normalized()
+        let config_dir = path
+            .parent()
+            .ok_or_else(|| AppError::Settings("invalid settings path".into()))?;
+        let secrets = SecretStore::new(config_dir);
+        let mut load_warning = None;
+        let mut rewrite = false;
+        let mut data = if path.exists() {
+            match fs::read_to_string(&path) {
+                Ok(raw) => match parse_settings(&raw) {
+                    Ok((settings, migrated_from)) => {
+                        if let Some(from) = migrated_from {
+                            info!(from, to = SETTINGS_VERSION, "migrated settings schema");
+                            rewrite = true;
+                        }
+                        settings
+                    }
+                    Err(err) => {
+                        warn!(error = %err, "settings file is corrupt, using defaults");
+                        load_warning = Some(reset_corrupt_file(&path, &err)?);
+                        AppSettings::default()
+                    }
+                },
+                Err(err) => {
+                    // Leave an unreadable file alone; it may be a transient
+                    // permission problem rather than corruption.
+                    warn!(error = %err, "unable to read settings file, using defaults");
+                    load_warning = Some(format!(
+                        "Settings could not be read ({err}); defaults are in use for this session."
+                    ));
+                    AppSettings::default()
+                }
+            }
         } else {
             let defaults = AppSettings::default();
             if let Some(parent) = path.parent() {
                 fs::create_dir_all(parent).map_err(|err| AppError::Settings(err.to_string()))?;
             }
-            let body = serde_json::to_string_pretty(&defaults)
-                .map_err(|err| AppError::Settings(err.to_string()))?;
-            fs::write(&path, body).map_err(|err| AppError::Settings(err.to_string()))?;
+            write_settings_file(&path, &defaults)?;
             defaults
         };
+
+        let legacy_key = data.api_key.trim().to_string();
+        let mut api_key_warning = None;
+        if legacy_key.is_empty() {
+            data.api_key = match secrets.get(API_KEY_SECRET) {
+                Ok(key) => key.unwrap_or_default(),
+                Err(err) => {
+                    warn!(error = %err, "unable to read API key from secret storage");
+                    String::new()
+                }
+            };
+        } else {
+            // Older builds stored the key in plaintext; move it and rewrite the
+            // file, which no longer serializes the key.
+            match secrets.set(API_KEY_SECRET, &legacy_key) {
+                Ok(()) => {
+                    rewrite = true;
+                    info!("migrated API key from settings.json to secret storage");
+                }
+                Err(err) => {
+                    warn!(error = %err, "unable to migrate API key to secret storage");
+                    api_key_warning = Some(format!(
+                        "The API key could not be moved to secure storage ({err}) and is still \
+                         stored in settings.json. Saving settings retries the move."
+                    ));
+                }
+            }
+            data.api_key = legacy_key;
+        }
+        if rewrite {
+            let legacy_key = api_key_warning.is_some().then_some(data.api_key.as_str());
+            write_settings_file_with_key(&path, &data, legacy_key)?;
+        }
+
         Ok(Self {
             path,
+            secrets,
             inner: RwLock::new(data),
+            load_warning,
+            api_key_warning: Mutex::new(api_key_warning),
         })
     }
 
+    /// The credential store shared with other components.
+    pub fn secrets(&self) -> SecretStore {
+        self.secrets.clone()
+    }
+
     pub async fn get(&self) -> AppSettings {
         self.inner.read().await.clone()
     }
 
+    pub async fn view(&self) -> SettingsView {
+        let settings = self.get().await;
+        SettingsView {
+            api_key_set: !settings.api_key.is_empty(),
+            api_key_masked: mask_secret(&settings.api_key),
+            load_warning: self.load_warning.clone(),
+            api_key_warning: self.api_key_warning.lock().clone(),
+            settings,
+        }
+    }
+
+    /// Replaces the settings. The write lock is held until the file is on
+    /// disk, so concurrent saves are serialized and memory only changes once
+    /// persisting succeeded.
     pub async fn update(&self, new_settings: AppSettings) -> AppResult<()> {
-        let next = new_settings.normalized();
-        {
-            let mut guard = self.inner.write().await;
-            *guard = next.clone();
-        }
-        let payload =
-            serde_json::to_vec_pretty(&next).map_err(|err| AppError::Settings(err.to_string()))?;
-        tokio::fs::write(&self.path, payload)
-            .await
-            .map_err(|err| AppError::Settings(err.to_string()))?;
+        if new_settings.engine == TranscriptionEngine::Local {
+            crate::local::ensure_available()?;
+        }
+        let mut guard = self.inner.write().await;
+        let mut next = new_settings.normalized();
+        let current_key = guard.api_key.clone();
+        let incoming_key = next.api_key.trim().to_string();
+        // The webview only knows the masked key, so an empty or masked value
+        // means "unchanged".
+        let key_changed = !incoming_key.is_empty()
+            && incoming_key != mask_secret(&current_key)
+            && incoming_key != current_key;
+        next.api_key = if key_changed {
+            self.store_api_key(Some(incoming_key.clone())).await?;
+            // The new key replaces one still awaiting migration
+            self.api_key_warning.lock().take();
+            incoming_key
+        } else {
+            current_key.clone()
+        };
+
+        if let Err(err) = self.persist(&next).await {
+            if key_changed {
+                let previous = (!current_key.is_empty()).then_some(current_key);
+                if let Err(restore_err) = self.store_api_key(previous).await {
+                    warn!(error = %restore_err, "unable to restore previous API key");
+                }
+            }
+            return Err(err);
+        }
+        *guard = next;
         Ok(())
     }
+
+    /// Applies `change` to the current settings under the same lock as
+    /// `update`, so it cannot overwrite a concurrent save with stale data.
+    pub async fn modify(&self, change: impl FnOnce(&mut AppSettings)) -> AppResult<AppSettings> {
+        let mut guard = self.inner.write().await;
+        let mut next = guard.clone();
+        change(&mut next);
+        let next = next.normalized();
+        self.persist(&next).await?;
+        *guard = next.clone();
+        Ok(next)
+    }
+
+    pub async fn clear_api_key(&self) -> AppResult<()> {
+        let mut guard = self.inner.write().await;
+        self.store_api_key(None).await?;
+        guard.api_key.clear();
+        if self.api_key_warning.lock().take().is_some() {
+            // Scrub the plaintext key that was awaiting migration
+            self.persist(&guard).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the settings file. A key awaiting migration is moved to secret
+    /// storage first, and stays in the file if that fails again.
+    async fn persist(&self, settings: &AppSettings) -> AppResult<()> {
+        let pending = self.api_key_warning.lock().is_some();
+        if pending {
+            match self.store_api_key(Some(settings.api_key.clone())).await {
+                Ok(()) => {
+                    self.api_key_warning.lock().take();
+                    info!("migrated API key from settings.json to secret storage");
+                }
+                Err(err) => warn!(error = %err, "unable to migrate API key to secret storage"),
+            }
+        }
+        let path = self.path.clone();
+        let settings = settings.clone();
+        let legacy_key = self
+            .api_key_warning
+            .lock()
+            .is_some()
+            .then(|| settings.api_key.clone());
+        tokio::task::spawn_blocking(move || {
+            write_settings_file_with_key(&path, &settings, legacy_key.as_deref())
+        })
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?
+    }
+
+    async fn store_api_key(&self, key: Option<String>) -> AppResult<()> {
+        let secrets = self.secrets.clone();
+        tokio::task::spawn_blocking(move || match key {
+            Some(key) => secrets.set(API_KEY_SECRET, &key),
+            None => secrets.delete(API_KEY_SECRET),
+        })
+        .await
+        .map_err(|err| AppError::Internal(err.to_string()))?
+    }
+}
+
+/// Parses settings.json, running schema migrations as needed. Returns the
+/// version the file was migrated from, if any.
+fn parse_settings(raw: &str) -> Result<(AppSettings, Option<u32>), String> {
+    let value: Value = serde_json::from_str(raw).map_err(|err| err.to_string())?;
+    let Value::Object(mut object) = value else {
+        return Err("expected a JSON object".into());
+    };
+    let version = object
+        .get("version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(0);
+    if version > SETTINGS_VERSION {
+        warn!(
+            version,
+            supported = SETTINGS_VERSION,
+            "settings written by a newer build; unknown fields are ignored"
+        );
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(&mut object);
+    }
+    let settings = serde_json::from_value::<AppSettings>(Value::Object(object))
+        .map_err(|err| err.to_string())?
+        .normalized();
+    let migrated_from = (version < SETTINGS_VERSION).then_some(version);
+    Ok((settings, migrated_from))
+}
+
+/// v0 stored "no preference" for the input device as an empty string.
+fn migrate_v0_to_v1(object: &mut Map<String, Value>) {
+    if let Some(Value::String(device)) = object.get("input_device") {
+        if device.trim().is_empty() {
+            object.insert("input_device".into(), Value::Null);
+        }
+    }
+    object.insert("version".into(), Value::from(1));
+}
+
+/// Moves an unparsable settings.json aside, writes defaults in its place and
+/// returns the warning shown to the user.
+fn reset_corrupt_file(path: &Path, err: &str) -> AppResult<String> {
+    let mut warning = format!("Settings could not be read ({err}) and were reset to defaults.");
+    if let Some(backup) = backup_corrupt_file(path) {
+        warning.push_str(&format!(" The old file was saved as {}.", backup.display()));
+    }
+    write_settings_file(path, &AppSettings::default())?;
+    Ok(warning)
+}
+
+fn backup_corrupt_file(path: &Path) -> Option<PathBuf> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let backup = path.with_extension(format!("json.corrupt-{stamp}"));
+    match fs::rename(path, &backup) {
+        Ok(()) => Some(backup),
+        Err(err) => {
+            warn!(error = %err, "unable to back up corrupt settings file");
+            None
+        }
+    }
+}
+
+fn write_settings_file(path: &Path, settings: &AppSettings) -> AppResult<()> {
+    write_settings_file_with_key(path, settings, None)
+}
+
+/// Like [`write_settings_file`], keeping a `legacy_key` that could not be moved
+/// to secret storage so that rewriting the file never loses it.
+fn write_settings_file_with_key(
+    path: &Path,
+    settings: &AppSettings,
+    legacy_key: Option<&str>,
+) -> AppResult<()> {
+    let body = match legacy_key {
+        None => serde_json::to_vec_pretty(settings),
+        Some(key) => serde_json::to_value(settings).and_then(|mut value| {
+            if let Value::Object(object) = &mut value {
+                object.insert("api_key".into(), Value::from(key));
+            }
+            serde_json::to_vec_pretty(&value)
+        }),
+    }
+    .map_err(|err| AppError::Settings(err.to_string()))?;
+    write_atomic(path, &body).map_err(|err| AppError::Settings(err.to_string()))
 }
 
 fn settings_path() -> AppResult<PathBuf> {
@@ -95,9 +596,35 @@ fn settings_path() -> AppResult<PathBuf> {
 
 impl AppSettings {
     pub fn normalized(mut self) -> Self {
+        self.version = SETTINGS_VERSION;
         if self.model.trim().is_empty() {
             self.model = DEFAULT_REALTIME_MODEL.into();
         }
+        self.endpoint.base_url = self.endpoint.base_url.trim().to_string();
+        if self.endpoint.base_url.is_empty() {
+            self.endpoint.base_url = DEFAULT_REALTIME_URL.into();
+        }
+        if self.endpoint.model_param.trim().is_empty() {
+            self.endpoint.model_param = default_model_param();
+        }
+        self.vad.trailing_silence_ms = self.vad.trailing_silence_ms.clamp(200, 10_000);
+        self.vad.speech_margin_db = self.vad.speech_margin_db.clamp(3.0, 30.0);
+        self.vad.server_threshold = self.vad.server_threshold.clamp(0.0, 1.0);
+        self.vad.prefix_padding_ms = self.vad.prefix_padding_ms.min(2_000);
+        self.audio_buffer.buffer_seconds = self.audio_buffer.buffer_seconds.clamp(1, 120);
+        self.audio_buffer.max_loss_percent = self.audio_buffer.max_loss_percent.clamp(0.0, 100.0);
+        self.warm_mic.preroll_ms = self.warm_mic.preroll_ms.clamp(100, 2_000);
+        self.warm_mic.idle_timeout_minutes = self.warm_mic.idle_timeout_minutes.clamp(1, 120);
+        // A zero limit would purge everything; treat it as unset
+        for limit in [
+            &mut self.retention.max_age_days,
+            &mut self.retention.max_entries,
+            &mut self.retention.max_db_size_mb,
+        ] {
+            if *limit == Some(0) {
+                *limit = None;
+            }
+        }
         #[cfg(target_os = "macos")]
         {
             if self.hotkey.contains("Alt") {
@@ -107,3 +634,96 @@ impl AppSettings {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("coolchatty-settings-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn v0_file_is_migrated_to_current_version() {
+        let raw = r#"{ "model": "gpt-4o-transcribe", "input_device": "  ", "auto_paste": false }"#;
+        let (settings, migrated_from) = parse_settings(raw).unwrap();
+        assert_eq!(migrated_from, Some(0));
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.input_device, None);
+        assert_eq!(settings.model, "gpt-4o-transcribe");
+        assert!(!settings.auto_paste);
+    }
+
+    #[test]
+    fn current_file_is_not_migrated() {
+        let raw = format!(r#"{{ "version": {SETTINGS_VERSION}, "input_device": "alsa:Mic#1" }}"#);
+        let (settings, migrated_from) = parse_settings(&raw).unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(settings.input_device.as_deref(), Some("alsa:Mic#1"));
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let raw = format!(
+            r#"{{ "version": {SETTINGS_VERSION}, "save_history": false, "vad": {{ "trailing_silence_ms": 800 }} }}"#
+        );
+        let (settings, _) = parse_settings(&raw).unwrap();
+        let defaults = AppSettings::default();
+        assert!(!settings.save_history);
+        assert_eq!(settings.vad.trailing_silence_ms, 800);
+        assert_eq!(settings.vad.speech_margin_db, defaults.vad.speech_margin_db);
+        assert_eq!(settings.vad.mode, defaults.vad.mode);
+        assert_eq!(settings.model, defaults.model);
+        assert_eq!(settings.hotkey, defaults.hotkey);
+        assert_eq!(settings.sample_rate, defaults.sample_rate);
+        assert_eq!(settings.audio_buffer, defaults.audio_buffer);
+        assert_eq!(settings.warm_mic, defaults.warm_mic);
+        assert_eq!(settings.retention, defaults.retention);
+    }
+
+    #[test]
+    fn corrupt_file_is_backed_up_and_reset() {
+        let dir = scratch_dir("corrupt");
+        let path = dir.join("settings.json");
+        fs::write(&path, "{ not json").unwrap();
+        let err = parse_settings("{ not json").unwrap_err();
+
+        let warning = reset_corrupt_file(&path, &err).unwrap();
+        assert!(warning.contains("reset to defaults"), "{warning}");
+
+        let backups: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p != &path)
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "{ not json");
+
+        let (reset, migrated_from) = parse_settings(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(reset.model, AppSettings::default().model);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn api_key_is_never_written_to_disk() {
+        let dir = scratch_dir("api-key");
+        let path = dir.join("settings.json");
+        let settings = AppSettings {
+            api_key: "sk-test-secret".into(),
+            ..AppSettings::default()
+        };
+
+        write_settings_file(&path, &settings).unwrap();
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("api_key"), "{raw}");
+        assert!(!raw.contains("sk-test-secret"), "{raw}");
+        let (reloaded, _) = parse_settings(&raw).unwrap();
+        assert!(reloaded.api_key.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+
+use crate::errors::{AppError, AppResult};
+use crate::local::{LocalWhisperBackend, WHISPER_SAMPLE_RATE};
+use crate::queue::AudioReceiver;
+use crate::realtime::{RealtimeBackend, REALTIME_SAMPLE_RATE};
+use crate::settings::{AppSettings, AuthScheme, TranscriptionEngine, VadSettings};
+use crate::vad::VadCallback;
+
+/// Incremental transcript text pushed to the UI while a session is running.
+/// Interim updates carry only the new `delta`; the UI appends them in
+/// `sequence` order. The final update carries the whole `text` and is sent
+/// whether the session succeeded or failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptUpdate {
+    pub session_id: u64,
+    pub sequence: u64,
+    pub delta: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub is_final: bool,
+}
+
+pub type TranscriptSink = Arc<dyn Fn(TranscriptUpdate) + Send + Sync>;
+
+/// Per-session parameters handed to a backend alongside the audio stream.
+pub struct SessionContext {
+    pub sample_rate: u32,
+    pub session_id: u64,
+    pub sink: TranscriptSink,
+    /// Server-side turn detection; backends without one ignore it.
+    pub turn_detection: Option<VadSettings>,
+    /// Receives speech and end-of-utterance events from server turn detection.
+    pub vad_events: VadCallback,
+}
+
+/// Turns a stream of mono PCM16 chunks into a transcript. The stream ends when
+/// the recorder stops, after which the backend returns the final text.
+pub trait TranscriptionBackend: Send {
+    fn name(&self) -> &'static str;
+
+    /// Rate of the PCM16 audio the backend expects; the recorder resamples to it.
+    fn sample_rate(&self) -> u32;
+
+    fn transcribe(
+        self: Box<Self>,
+        audio_rx: AudioReceiver,
+        ctx: SessionContext,
+    ) -> BoxFuture<'static, AppResult<String>>;
+}
+
+/// Rate the engine's backend expects, known without constructing it.
+pub fn engine_sample_rate(engine: TranscriptionEngine) -> u32 {
+    match engine {
+        TranscriptionEngine::Realtime => REALTIME_SAMPLE_RATE,
+        TranscriptionEngine::Local => WHISPER_SAMPLE_RATE,
+    }
+}
+
+pub fn backend_for(settings: &AppSettings) -> AppResult<Box<dyn TranscriptionBackend>> {
+    match settings.engine {
+        TranscriptionEngine::Realtime => {
+            if settings.api_key.trim().is_empty()
+                && settings.endpoint.auth_scheme != AuthScheme::None
+            {
+                return Err(AppError::MissingApiKey);
+            }
+            Ok(Box::new(RealtimeBackend::new(
+                settings.api_key.clone(),
+                settings.model.clone(),
+                settings.endpoint.clone(),
+            )))
+        }
+        TranscriptionEngine::Local => {
+            crate::local::ensure_available()?;
+            let model_path = settings
+                .local_model_path
+                .as_deref()
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| {
+                    AppError::Validation("No local Whisper model file configured".into())
+                })?;
+            Ok(Box::new(LocalWhisperBackend::new(
+                model_path.into(),
+                settings.local_language.clone(),
+            )))
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+/// Analysis frame length. Short enough to react quickly, long enough for a
+/// stable energy estimate.
+const FRAME_MS: u32 = 20;
+/// Consecutive speech frames needed before speech is reported, so clicks and
+/// key presses do not start a segment.
+const ONSET_FRAMES: u32 = 3;
+/// Silence tolerated inside speech before the segment is closed.
+const HANGOVER_MS: u32 = 300;
+/// Frames quieter than this are never speech, whatever the noise floor.
+const ABSOLUTE_FLOOR_DB: f32 = -55.0;
+/// Lowest noise floor tracked; digital silence would otherwise pull it to
+/// minus infinity.
+const MIN_NOISE_FLOOR_DB: f32 = -90.0;
+/// How quickly the noise floor rises towards louder background noise.
+const FLOOR_RISE: f32 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    SpeechStarted,
+    SpeechEnded,
+    /// Trailing silence after speech reached the configured limit. Reported
+    /// once per session.
+    EndOfUtterance,
+}
+
+pub type VadCallback = Arc<dyn Fn(VadEvent) + Send + Sync>;
+
+/// Energy-based voice activity detector with an adaptive noise floor. A frame
+/// is speech when its level is `margin_db` above the floor and above
+/// `ABSOLUTE_FLOOR_DB`; the floor follows the background while nobody speaks.
+pub struct EnergyVad {
+    frame_len: usize,
+    frame_energy: f64,
+    frame_fill: usize,
+    margin_db: f32,
+    noise_floor_db: Option<f32>,
+    speaking: bool,
+    onset_frames: u32,
+    silence_ms: u32,
+    trailing_silence_ms: u32,
+    heard_speech: bool,
+    utterance_ended: bool,
+}
+
+impl EnergyVad {
+    pub fn new(sample_rate: u32, trailing_silence_ms: u32, margin_db: f32) -> Self {
+        Self {
+            frame_len: (sample_rate * FRAME_MS / 1000).max(1) as usize,
+            frame_energy: 0.0,
+            frame_fill: 0,
+            margin_db,
+            noise_floor_db: None,
+            speaking: false,
+            onset_frames: 0,
+            silence_ms: 0,
+            trailing_silence_ms,
+            heard_speech: false,
+            utterance_ended: false,
+        }
+    }
+
+    /// Feeds mono PCM16 samples and reports segment transitions through `emit`.
+    pub fn process(&mut self, samples: &[i16], mut emit: impl FnMut(VadEvent)) {
+        for &sample in samples {
+            let value = f64::from(sample) / f64::from(i16::MAX);
+            self.frame_energy += value * value;
+            self.frame_fill += 1;
+            if self.frame_fill == self.frame_len {
+                let mean = self.frame_energy / self.frame_len as f64;
+                let level_db = (10.0 * (mean + 1e-10).log10()) as f32;
+                self.frame_energy = 0.0;
+                self.frame_fill = 0;
+                self.on_frame(level_db, &mut emit);
+            }
+        }
+    }
+
+    fn on_frame(&mut self, level_db: f32, emit: &mut impl FnMut(VadEvent)) {
+        let floor = *self
+            .noise_floor_db
+            .get_or_insert(level_db.max(MIN_NOISE_FLOOR_DB));
+        let is_speech = level_db > ABSOLUTE_FLOOR_DB && level_db > floor + self.margin_db;
+
+        if !self.speaking {
+            // Drop to quieter levels at once, rise slowly towards louder ones
+            let next = if level_db < floor {
+                level_db
+            } else {
+                floor + (level_db - floor) * FLOOR_RISE
+            };
+            self.noise_floor_db = Some(next.max(MIN_NOISE_FLOOR_DB));
+        }
+
+        if is_speech {
+            self.silence_ms = 0;
+            if !self.speaking {
+                self.onset_frames += 1;
+                if self.onset_frames >= ONSET_FRAMES {
+                    self.speaking = true;
+                    self.heard_speech = true;
+                    emit(VadEvent::SpeechStarted);
+                }
+            }
+            return;
+        }
+
+        self.onset_frames = 0;
+        if !self.heard_speech {
+            return;
+        }
+        self.silence_ms = self.silence_ms.saturating_add(FRAME_MS);
+        if self.speaking && self.silence_ms >= HANGOVER_MS {
+            self.speaking = false;
+            emit(VadEvent::SpeechEnded);
+        }
+        if !self.utterance_ended && self.silence_ms >= self.trailing_silence_ms {
+            self.utterance_ended = true;
+            emit(VadEvent::EndOfUtterance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+    const FRAME: usize = (RATE * FRAME_MS / 1000) as usize;
+    /// Roughly -61 dBFS, a quiet room.
+    const NOISE: i16 = 30;
+    /// Roughly -12 dBFS, normal speech.
+    const SPEECH: i16 = 8_000;
+
+    /// A frame of alternating samples at a constant level.
+    fn frame(amplitude: i16) -> Vec<i16> {
+        (0..FRAME)
+            .map(|i| if i % 2 == 0 { amplitude } else { -amplitude })
+            .collect()
+    }
+
+    /// Feeds `count` frames and returns each event with the 1-based index of
+    /// the frame that produced it.
+    fn feed(vad: &mut EnergyVad, amplitude: i16, count: usize) -> Vec<(usize, VadEvent)> {
+        let frame = frame(amplitude);
+        let mut events = Vec::new();
+        for index in 1..=count {
+            vad.process(&frame, |event| events.push((index, event)));
+        }
+        events
+    }
+
+    fn vad() -> EnergyVad {
+        EnergyVad::new(RATE, 1_000, 10.0)
+    }
+
+    #[test]
+    fn speech_starts_after_consecutive_onset_frames() {
+        let mut vad = vad();
+        assert!(feed(&mut vad, NOISE, 10).is_empty());
+        // A click shorter than the onset does not start speech
+        assert!(feed(&mut vad, SPEECH, ONSET_FRAMES as usize - 1).is_empty());
+        assert!(feed(&mut vad, NOISE, 1).is_empty());
+        assert_eq!(
+            feed(&mut vad, SPEECH, 10),
+            vec![(ONSET_FRAMES as usize, VadEvent::SpeechStarted)]
+        );
+    }
+
+    #[test]
+    fn short_pauses_are_bridged_by_the_hangover() {
+        let mut vad = vad();
+        feed(&mut vad, NOISE, 10);
+        feed(&mut vad, SPEECH, 10);
+        let hangover_frames = (HANGOVER_MS / FRAME_MS) as usize;
+
+        assert!(feed(&mut vad, NOISE, hangover_frames - 1).is_empty());
+        assert!(feed(&mut vad, SPEECH, 5).is_empty());
+        assert_eq!(
+            feed(&mut vad, NOISE, hangover_frames),
+            vec![(hangover_frames, VadEvent::SpeechEnded)]
+        );
+    }
+
+    #[test]
+    fn end_of_utterance_follows_trailing_silence_once() {
+        let mut vad = vad();
+        feed(&mut vad, NOISE, 10);
+        feed(&mut vad, SPEECH, 10);
+        let hangover_frames = (HANGOVER_MS / FRAME_MS) as usize;
+        let trailing_frames = (1_000 / FRAME_MS) as usize;
+
+        assert_eq!(
+            feed(&mut vad, NOISE, trailing_frames),
+            vec![
+                (hangover_frames, VadEvent::SpeechEnded),
+                (trailing_frames, VadEvent::EndOfUtterance),
+            ]
+        );
+
+        let mut later = feed(&mut vad, SPEECH, 10);
+        later.extend(feed(&mut vad, NOISE, 2 * trailing_frames));
+        assert!(!later.iter().any(|(_, e)| *e == VadEvent::EndOfUtterance));
+    }
+
+    #[test]
+    fn silence_alone_reports_nothing() {
+        let mut vad = vad();
+        assert!(feed(&mut vad, NOISE, 200).is_empty());
+        assert!(feed(&mut vad, 0, 200).is_empty());
+    }
+
+    #[test]
+    fn frames_split_across_calls_are_joined() {
+        let mut vad = vad();
+        feed(&mut vad, NOISE, 10);
+        let speech: Vec<i16> = (0..ONSET_FRAMES).flat_map(|_| frame(SPEECH)).collect();
+        let mut events = Vec::new();
+        for chunk in speech.chunks(FRAME / 3 + 1) {
+            vad.process(chunk, |event| events.push(event));
+        }
+        assert_eq!(events, vec![VadEvent::SpeechStarted]);
+    }
+}
@@ -0,0 +1,64 @@
+//! The audio callback runs on the device's real-time thread, where an
+//! allocation can block long enough to drop frames.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+#[allow(dead_code)]
+#[path = "../src/queue.rs"]
+mod queue;
+#[allow(dead_code)]
+#[path = "../src/sample.rs"]
+mod sample;
+
+use queue::CaptureRing;
+
+/// Counts allocations per thread, so the test harness's own threads do not
+/// interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn capture_callback_does_not_allocate() {
+    let ring = CaptureRing::new(48_000);
+    let mut drained = Vec::with_capacity(48_000);
+    let floats: Vec<f32> = (0..2048).map(|n| (n as f32 * 0.01).sin()).collect();
+    let ints: Vec<i16> = floats.iter().map(|x| (x * 32_767.0) as i16).collect();
+    for frames in [128, 480, 1024] {
+        let before = allocations();
+        ring.push_frames(&floats[..frames * 2], 2, None);
+        ring.push_frames(&floats[..frames * 2], 2, Some(1));
+        ring.push_frames(&ints[..frames * 2], 2, None);
+        assert_eq!(allocations(), before, "{frames} frames");
+        ring.drain_into(&mut drained);
+        drained.clear();
+    }
+
+    // Nor when the bridge thread falls behind and samples are discarded
+    let full = CaptureRing::new(16);
+    let before = allocations();
+    full.push_frames(&floats, 2, None);
+    assert_eq!(allocations(), before);
+    assert_eq!(full.overflowed(), 1024 - 16);
+}